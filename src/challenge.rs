@@ -0,0 +1,461 @@
+use serenity::builder::CreateApplicationCommands;
+use serenity::client::Context;
+use serenity::framework::standard::{
+    macros::{command, group},
+    Args, CommandResult,
+};
+use serenity::model::application::command::CommandOptionType;
+use serenity::model::application::interaction::application_command::CommandDataOption;
+use serenity::model::channel::Message;
+use serenity::model::id::ChannelId;
+use serenity::model::user::User;
+use serenity::prelude::Mentionable;
+
+use rusqlite::{params, Connection, OptionalExtension, Result, Row};
+
+use crate::combat::{self, Bout};
+use crate::slash::{
+    gladiator_option, interactive_option, option_bool, option_int, option_str, option_user,
+    Invocation,
+};
+use crate::{
+    find_character, get_character, hall, in_guild, is_owner, matchmaking, narrate, now, open_db,
+    purse,
+};
+
+const CHALLENGE_MINUTES: i64 = 10;
+
+#[group]
+#[commands(challenge, accept, decline)]
+struct Challenges;
+
+#[derive(Clone, Debug)]
+struct Challenge {
+    id: i64,
+    guild_id: Option<u64>,
+    channel_id: u64,
+    challenger_id: u64,
+    glad_id: i64,
+    challenged_id: u64,
+    wager: i64,
+}
+
+fn open() -> Result<Connection> {
+    let db = open_db()?;
+
+    match db.execute_batch(
+        "
+    CREATE TABLE IF NOT EXISTS challenges (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        guild_id INTEGER,
+        channel_id INTEGER,
+        challenger_id INTEGER,
+        glad_id INTEGER,
+        challenged_id INTEGER,
+        wager INTEGER,
+        status STRING,
+        expires_at INTEGER
+    );",
+    ) {
+        Ok(result) => result,
+        Err(e) => {
+            println!("error creating challenge table: {}", e);
+            return Err(e);
+        }
+    };
+
+    Ok(db)
+}
+
+fn row_to_challenge(row: &Row) -> Result<Challenge> {
+    Ok(Challenge {
+        id: row.get(0)?,
+        guild_id: row.get(1)?,
+        channel_id: row.get(2)?,
+        challenger_id: row.get(3)?,
+        glad_id: row.get(4)?,
+        challenged_id: row.get(5)?,
+        wager: row.get(6)?,
+    })
+}
+
+// The latest challenge still waiting on this player's answer in the channel
+fn get_pending(db: &Connection, channel_id: u64, challenged_id: u64) -> Result<Option<Challenge>> {
+    db.query_row(
+        "SELECT id, guild_id, channel_id, challenger_id, glad_id, challenged_id, wager FROM challenges
+        WHERE channel_id = ?1 AND challenged_id = ?2 AND status = 'pending' AND expires_at > ?3
+        ORDER BY id DESC LIMIT 1",
+        params![channel_id, challenged_id, now()],
+        row_to_challenge,
+    )
+    .optional()
+}
+
+fn get_expired(db: &Connection) -> Result<Vec<Challenge>> {
+    let mut stmt = db.prepare(
+        "SELECT id, guild_id, channel_id, challenger_id, glad_id, challenged_id, wager FROM challenges
+        WHERE status = 'pending' AND expires_at <= ?1",
+    )?;
+    let rows = stmt.query_map([now()], row_to_challenge)?;
+    rows.collect()
+}
+
+// Moves a challenge on from pending, returning false if something else
+// already answered it
+fn claim(db: &Connection, challenge: &Challenge, status: &str) -> Result<bool> {
+    let updated = db.execute(
+        "UPDATE challenges SET status = ?1 WHERE id = ?2 AND status = 'pending'",
+        params![status, challenge.id],
+    )?;
+
+    Ok(updated == 1)
+}
+
+// Closes a challenge that never came to blows, handing back the stake.
+// Returns false if it had already been answered
+fn close(db: &Connection, challenge: &Challenge, status: &str) -> Result<bool> {
+    if !claim(db, challenge, status)? {
+        return Ok(false);
+    }
+    if let (Some(guild_id), true) = (challenge.guild_id, challenge.wager > 0) {
+        purse::credit(guild_id, challenge.challenger_id, challenge.wager)?;
+    }
+
+    Ok(true)
+}
+
+// Called by the scheduler to withdraw challenges nobody answered in time
+pub async fn tick(ctx: &Context) {
+    let db = match open() {
+        Ok(db) => db,
+        Err(e) => {
+            println!("error opening challenges: {}", e);
+            return;
+        }
+    };
+    let expired = match get_expired(&db) {
+        Ok(expired) => expired,
+        Err(e) => {
+            println!("error loading challenges: {}", e);
+            return;
+        }
+    };
+
+    for challenge in expired {
+        match close(&db, &challenge, "expired") {
+            Ok(true) => {}
+            Ok(false) => continue,
+            Err(e) => {
+                println!("error expiring challenge {}: {}", challenge.id, e);
+                continue;
+            }
+        }
+        let out = format!(
+            "<@{}>'s challenge to <@{}> went unanswered and has been withdrawn.",
+            challenge.challenger_id, challenge.challenged_id
+        );
+        if let Err(e) = ChannelId(challenge.channel_id).say(&ctx.http, &out).await {
+            println!("error posting expired challenge: {}", e);
+        }
+    }
+}
+
+pub fn register(commands: &mut CreateApplicationCommands) -> &mut CreateApplicationCommands {
+    commands
+        .create_application_command(|command| {
+            command
+                .name("challenge")
+                .description("Challenge another player to a fight to the death")
+                .create_option(|option| {
+                    option
+                        .name("player")
+                        .description("Who to challenge")
+                        .kind(CommandOptionType::User)
+                        .required(true)
+                })
+                .create_option(|option| gladiator_option(option, "Your gladiator", true))
+                .create_option(|option| {
+                    option
+                        .name("wager")
+                        .description("Denarii each side puts up")
+                        .kind(CommandOptionType::Integer)
+                        .min_int_value(0)
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("accept")
+                .description("Accept the challenge waiting on you in this channel")
+                .create_option(|option| gladiator_option(option, "Gladiator to send out", true))
+                .create_option(interactive_option)
+        })
+        .create_application_command(|command| {
+            command
+                .name("decline")
+                .description("Decline the challenge waiting on you in this channel")
+        })
+}
+
+pub async fn run(inv: &Invocation<'_>, name: &str, options: &[CommandDataOption]) -> CommandResult {
+    let gladiator = option_str(options, "gladiator").unwrap_or("");
+    match name {
+        "challenge" => {
+            let wager = option_int(options, "wager").unwrap_or(0).max(0);
+            issue_challenge(inv, option_user(options, "player"), gladiator, wager).await
+        }
+        "accept" => {
+            let interactive = option_bool(options, "interactive").unwrap_or(false);
+            accept_challenge(inv, gladiator, interactive).await
+        }
+        "decline" => decline_challenge(inv).await,
+        _ => Ok(()),
+    }
+}
+
+#[command]
+async fn challenge(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    // Whatever isn't the mention is the gladiator's name, with the wager last
+    let mut words: Vec<&str> = args.raw().filter(|arg| !arg.starts_with("<@")).collect();
+    let wager = match words.last().map(|word| word.parse::<i64>()) {
+        Some(Ok(wager)) => {
+            words.pop();
+            wager.max(0)
+        }
+        _ => 0,
+    };
+
+    issue_challenge(
+        &Invocation::from_message(ctx, msg),
+        msg.mentions.first(),
+        &words.join(" "),
+        wager,
+    )
+    .await
+}
+
+async fn issue_challenge(
+    inv: &Invocation<'_>,
+    challenged: Option<&User>,
+    name: &str,
+    wager: i64,
+) -> CommandResult {
+    let challenged = match challenged {
+        Some(user) if user.id != inv.author.id && !user.bot => user,
+        _ => {
+            inv.reply("Challenge whom? `/challenge <player> <your gladiator> [wager]`")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let glad = match find_character(
+        name,
+        inv.guild_id.map(|guild_id| guild_id.0),
+        inv.author.id.0,
+    )? {
+        Some(glad) if !in_guild(&glad, inv.guild_id.map(|guild_id| guild_id.0)) => {
+            let out = format!("{} fights in another arena.", glad.name);
+            inv.reply(&out).await?;
+            return Ok(());
+        }
+        Some(glad) if !is_owner(&glad, inv.author.id.0) => {
+            let out = format!("{} does not fight for you!", glad.name);
+            inv.reply(&out).await?;
+            return Ok(());
+        }
+        Some(glad) if !matchmaking::can_fight(&glad) => {
+            let out = format!("{} is in no state to fight!", glad.name);
+            inv.reply(&out).await?;
+            return Ok(());
+        }
+        Some(glad) => glad,
+        None => {
+            inv.reply("No gladiator goes by that name.").await?;
+            return Ok(());
+        }
+    };
+
+    let guild_id = inv.guild_id.map(|guild_id| guild_id.0);
+    if wager > 0 {
+        let staked = match guild_id {
+            Some(guild_id) => purse::debit(guild_id, inv.author.id.0, wager)?,
+            None => false,
+        };
+        if !staked {
+            let out = format!("You can't cover a wager of {} denarii.", wager);
+            inv.reply(&out).await?;
+            return Ok(());
+        }
+    }
+
+    let db = open()?;
+    db.execute(
+        "INSERT INTO challenges VALUES (NULL, ?1, ?2, ?3, ?4, ?5, ?6, 'pending', ?7)",
+        params![
+            guild_id,
+            inv.channel_id.0,
+            inv.author.id.0,
+            glad.id,
+            challenged.id.0,
+            wager,
+            now() + CHALLENGE_MINUTES * 60
+        ],
+    )?;
+
+    let stakes = match wager {
+        0 => String::new(),
+        wager => format!(" for {} denarii", wager),
+    };
+    let out = format!(
+        "{}, {} challenges you to send a gladiator against {}{}! Answer with `/accept <gladiator>` or `/decline` within {} minutes.",
+        challenged.mention(),
+        inv.author.name,
+        glad.name,
+        stakes,
+        CHALLENGE_MINUTES
+    );
+    inv.reply(&out).await?;
+
+    Ok(())
+}
+
+#[command]
+async fn accept(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    accept_challenge(&Invocation::from_message(ctx, msg), args.rest(), false).await
+}
+
+async fn accept_challenge(inv: &Invocation<'_>, name: &str, interactive: bool) -> CommandResult {
+    let db = open()?;
+    let challenge = match get_pending(&db, inv.channel_id.0, inv.author.id.0)? {
+        Some(challenge) => challenge,
+        None => {
+            inv.reply("Nobody has challenged you here.").await?;
+            return Ok(());
+        }
+    };
+
+    let glad = match find_character(
+        name,
+        inv.guild_id.map(|guild_id| guild_id.0),
+        inv.author.id.0,
+    )? {
+        Some(glad) if !in_guild(&glad, inv.guild_id.map(|guild_id| guild_id.0)) => {
+            let out = format!("{} fights in another arena.", glad.name);
+            inv.reply(&out).await?;
+            return Ok(());
+        }
+        Some(glad) if !is_owner(&glad, inv.author.id.0) || glad.id == challenge.glad_id => {
+            let out = format!("{} does not fight for you!", glad.name);
+            inv.reply(&out).await?;
+            return Ok(());
+        }
+        Some(glad) if !matchmaking::can_fight(&glad) => {
+            let out = format!("{} is in no state to fight!", glad.name);
+            inv.reply(&out).await?;
+            return Ok(());
+        }
+        Some(glad) => glad,
+        None => {
+            inv.reply("Accept with which gladiator? `/accept <gladiator>`")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let challenger = get_character(challenge.glad_id)?;
+    if !matchmaking::can_fight(&challenger) {
+        close(&db, &challenge, "withdrawn")?;
+        let out = format!(
+            "{} is no longer fit to fight, the challenge is off.",
+            challenger.name
+        );
+        inv.reply(&out).await?;
+        return Ok(());
+    }
+
+    // Claimed before any denarii move, so an expiry or a second accept
+    // can't pay out the same stake twice
+    if !claim(&db, &challenge, "accepted")? {
+        inv.reply("That challenge has already been answered.")
+            .await?;
+        return Ok(());
+    }
+    if challenge.wager > 0 {
+        let staked = match challenge.guild_id {
+            Some(guild_id) => purse::debit(guild_id, inv.author.id.0, challenge.wager)?,
+            None => false,
+        };
+        if !staked {
+            db.execute(
+                "UPDATE challenges SET status = 'pending' WHERE id = ?1 AND status = 'accepted'",
+                [challenge.id],
+            )?;
+            let out = format!("You can't cover the wager of {} denarii.", challenge.wager);
+            inv.reply(&out).await?;
+            return Ok(());
+        }
+    }
+
+    let out = format!(
+        "The challenge is accepted! {} faces {}!",
+        challenger.name, glad.name
+    );
+    // The stakes are in, so from here on only the notices may fail
+    if let Err(e) = inv.reply(&out).await {
+        println!("error announcing challenge: {}", e);
+    }
+
+    let mut bout = Bout::new(challenger, glad);
+    bout.lethal = true;
+    narrate(inv, &mut bout, interactive).await;
+    combat::record(&bout)?;
+
+    let mut payout = None;
+    if let (Some(guild_id), Some(winner)) = (challenge.guild_id, bout.winner()) {
+        if challenge.wager > 0 {
+            let winner_id = match winner.id == challenge.glad_id {
+                true => challenge.challenger_id,
+                false => challenge.challenged_id,
+            };
+            purse::credit(guild_id, winner_id, challenge.wager * 2)?;
+            payout = Some(format!(
+                "<@{}> collects {} denarii!",
+                winner_id,
+                challenge.wager * 2
+            ));
+        }
+    }
+
+    if let Some(obituary) = hall::obituary(&bout)? {
+        inv.reply_embed(obituary).await?;
+    }
+    if let Some(out) = payout {
+        inv.reply(&out).await?;
+    }
+
+    Ok(())
+}
+
+#[command]
+async fn decline(ctx: &Context, msg: &Message) -> CommandResult {
+    decline_challenge(&Invocation::from_message(ctx, msg)).await
+}
+
+async fn decline_challenge(inv: &Invocation<'_>) -> CommandResult {
+    let db = open()?;
+    let out = match get_pending(&db, inv.channel_id.0, inv.author.id.0)? {
+        Some(challenge) if !close(&db, &challenge, "declined")? => {
+            "That challenge has already been answered.".to_string()
+        }
+        Some(challenge) => {
+            format!(
+                "{} declines <@{}>'s challenge.",
+                inv.author.name, challenge.challenger_id
+            )
+        }
+        None => "Nobody has challenged you here.".to_string(),
+    };
+    inv.reply(&out).await?;
+
+    Ok(())
+}
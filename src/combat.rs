@@ -0,0 +1,1171 @@
+use rand::seq::SliceRandom;
+use rusqlite::{params, Result};
+use std::cmp::Ordering;
+
+use crate::{
+    calc_modifier, get_character, get_hit_msg, get_weapon, ludus, now, open_db, rating, retirement,
+    roller, shield_bonus, Character,
+};
+
+// How long a gladiator beaten to the ground takes to be fit again, anyone
+// less hurt recovers in proportion to the damage they took
+const RECOVERY_HOURS: i64 = 24;
+
+// Bonus to AC for a gladiator who does nothing but defend this round
+const GUARD_BONUS: i8 = 4;
+// What an all-out mighty deed costs in AC for the round
+const DEED_EXPOSURE: i8 = 2;
+const AIM_BONUS: i8 = 2;
+// To-hit bonus with a weapon the gladiator has trained in
+const PROFICIENCY_BONUS: i8 = 1;
+// To-hit penalty on the next attack of a gladiator taunted into rage
+const TAUNT_PENALTY: i8 = 2;
+const TAUNT_DC: i8 = 12;
+
+// A deed die roll of this much or more pulls the declared deed off
+const DEED_SUCCESS: i8 = 3;
+// The deed die grows with level, from a d3 at first level up to a d8
+const DEED_DIE_MAX: i8 = 8;
+// To-hit penalty on the next attack of a gladiator with blood in their eyes
+const BLIND_PENALTY: i8 = 4;
+// What being driven back costs in AC, and gains the one pressing
+const PUSH_ADVANTAGE: i8 = 2;
+
+// Luck never burns below this, and no more than this goes on one roll
+const MIN_LUCK: i8 = 3;
+const MAX_LUCK_BURN: i8 = 5;
+
+// What a saving throw has to beat to shake off a condition
+const SAVE_DC: i8 = 12;
+// Dodging a deed gets harder the higher the deed die rolled
+const DEED_SAVE_DC: i8 = 10;
+
+// Each round a gladiator fails a Fortitude save against this, climbing a
+// little every few rounds, they tire a point, and every couple of points
+// costs them a point of attack and AC
+const FATIGUE_DC: i8 = 8;
+const FATIGUE_RAMP: i32 = 5;
+const FATIGUE_STEP: i8 = 2;
+// Past this the judges stop the bout and pick a winner
+const MAX_ROUNDS: i32 = 30;
+
+// A crit, or a blow taking half a gladiator's HP in one go, risks a lasting
+// injury unless they pass a Fortitude save
+const INJURY_DC: i8 = 8;
+// Injuries never take a stat below this
+const MIN_STAT: i8 = 3;
+
+// Prone, entangled and blind gladiators are easier to hit
+const EXPOSED_BONUS: i8 = 2;
+const BLINDED_ROUNDS: i8 = 2;
+// Archers dip their arrowheads, and the venom works for a few turns
+const POISON_ROUNDS: i8 = 3;
+
+// A gladiator goes up a level every few wins, and each level toughens them
+// by a d6 of HP plus Sta
+const WINS_PER_LEVEL: i64 = 3;
+const MAX_LEVEL: i8 = 10;
+const LEVEL_HIT_DIE: i8 = 6;
+
+// DCC saving throws, each resting on a stat
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Save {
+    // Stamina, against bleeding, stunning blows and poison
+    Fortitude,
+    // Agility, against trips, nets and sand in the eyes
+    Reflex,
+    // Personality, against fear and taunts
+    Willpower,
+}
+
+// Lingering effects on a combatant, lasting a number of their turns or until
+// they save against them
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Condition {
+    Bleeding,
+    Stunned,
+    Entangled,
+    Prone,
+    Blinded,
+    Poisoned,
+}
+
+impl Condition {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Condition::Bleeding => "bleeding",
+            Condition::Stunned => "stunned",
+            Condition::Entangled => "entangled",
+            Condition::Prone => "prone",
+            Condition::Blinded => "blinded",
+            Condition::Poisoned => "poisoned",
+        }
+    }
+}
+
+// Wounds that never fully heal, carried from bout to bout
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Injury {
+    LostEye,
+    Limp,
+    ScarredFace,
+    LostFingers,
+    CrackedRibs,
+}
+
+impl Injury {
+    pub const ALL: [Injury; 5] = [
+        Injury::LostEye,
+        Injury::Limp,
+        Injury::ScarredFace,
+        Injury::LostFingers,
+        Injury::CrackedRibs,
+    ];
+
+    pub fn parse(injury: &str) -> Option<Injury> {
+        Injury::ALL
+            .iter()
+            .find(|candidate| candidate.as_str() == injury)
+            .copied()
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Injury::LostEye => "lost eye",
+            Injury::Limp => "limp",
+            Injury::ScarredFace => "scarred face",
+            Injury::LostFingers => "lost fingers",
+            Injury::CrackedRibs => "cracked ribs",
+        }
+    }
+
+    // Strength, agility, stamina and personality the injury costs
+    fn penalties(&self) -> [i8; 4] {
+        match self {
+            Injury::LostEye => [0, -1, 0, -1],
+            Injury::Limp => [0, -2, 0, 0],
+            Injury::ScarredFace => [0, 0, 0, -2],
+            Injury::LostFingers => [-1, -1, 0, 0],
+            Injury::CrackedRibs => [0, 0, -2, 0],
+        }
+    }
+
+    // Broken bones knit and a limp can be worked out, the rest is for life
+    pub fn curable(&self) -> bool {
+        matches!(self, Injury::Limp | Injury::CrackedRibs)
+    }
+
+    pub fn apply(&self, glad: &mut Character) {
+        let [strength, agility, stamina, personality] = self.penalties();
+        for (stat, penalty) in [
+            (&mut glad.strength, strength),
+            (&mut glad.agility, agility),
+            (&mut glad.stamina, stamina),
+            (&mut glad.personality, personality),
+        ] {
+            *stat = (*stat + penalty).max(MIN_STAT.min(*stat));
+        }
+    }
+
+    fn narration(&self, name: &str) -> String {
+        match self {
+            Injury::LostEye => format!("{} loses an eye to the blow!", name),
+            Injury::Limp => format!("{}'s knee buckles, they'll walk with a limp!", name),
+            Injury::ScarredFace => format!("{}'s face is laid open to the bone!", name),
+            Injury::LostFingers => format!("{}'s fingers fly into the sand!", name),
+            Injury::CrackedRibs => format!("{}'s ribs crack under the blow!", name),
+        }
+    }
+}
+
+pub fn parse_injuries(injuries: &str) -> Vec<Injury> {
+    injuries.split("; ").filter_map(Injury::parse).collect()
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Affliction {
+    condition: Condition,
+    // Turns left, or None for until it's saved against
+    rounds: Option<i8>,
+}
+
+// Feats of arms a gladiator can declare with a mighty deed
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Deed {
+    Disarm,
+    Trip,
+    Blind,
+    Push,
+}
+
+// What a gladiator can do with their round
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Attack,
+    Defend,
+    Block,
+    Parry,
+    Dodge,
+    Aim,
+    Taunt,
+    MightyDeed,
+    Yield,
+}
+
+impl Action {
+    pub const ALL: [Action; 9] = [
+        Action::Attack,
+        Action::Defend,
+        Action::Block,
+        Action::Parry,
+        Action::Dodge,
+        Action::Aim,
+        Action::Taunt,
+        Action::MightyDeed,
+        Action::Yield,
+    ];
+
+    pub fn parse(action: &str) -> Option<Action> {
+        Action::ALL
+            .iter()
+            .find(|candidate| candidate.as_str() == action)
+            .copied()
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Action::Attack => "attack",
+            Action::Defend => "defend",
+            Action::Block => "block",
+            Action::Parry => "parry",
+            Action::Dodge => "dodge",
+            Action::Aim => "aim",
+            Action::Taunt => "taunt",
+            Action::MightyDeed => "deed",
+            Action::Yield => "yield",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Action::Attack => "Attack",
+            Action::Defend => "Defend",
+            Action::Block => "Shield Block",
+            Action::Parry => "Parry",
+            Action::Dodge => "Dodge",
+            Action::Aim => "Aim",
+            Action::Taunt => "Taunt",
+            Action::MightyDeed => "Mighty Deed",
+            Action::Yield => "Yield",
+        }
+    }
+}
+
+// How a gladiator fights when nobody is calling their actions
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Profile {
+    Aggressive,
+    Defensive,
+    Opportunist,
+    Showboat,
+}
+
+impl Profile {
+    pub const ALL: [Profile; 4] = [
+        Profile::Aggressive,
+        Profile::Defensive,
+        Profile::Opportunist,
+        Profile::Showboat,
+    ];
+
+    pub fn parse(profile: &str) -> Option<Profile> {
+        Profile::ALL
+            .iter()
+            .find(|candidate| candidate.as_str() == profile.to_lowercase())
+            .copied()
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Profile::Aggressive => "aggressive",
+            Profile::Defensive => "defensive",
+            Profile::Opportunist => "opportunist",
+            Profile::Showboat => "showboat",
+        }
+    }
+
+    // How each style is trained to fight until their owner says otherwise
+    pub fn for_style(style: &str) -> Profile {
+        match style {
+            "Murmillo" | "Provacator" | "Samnite" | "Hoplomachus" | "Cataphractarius" => {
+                Profile::Defensive
+            }
+            "Retiarius" | "Laquearius" | "Sagittarius" | "Velites" | "Thracian" => {
+                Profile::Opportunist
+            }
+            "Eques" | "Essedarius" | "Rudiarius" => Profile::Showboat,
+            _ => Profile::Aggressive,
+        }
+    }
+}
+
+// A fight between two gladiators, resolved one round at a time so callers
+// can narrate as it goes or just run it to the end.
+pub struct Bout {
+    pub gladiators: [Character; 2],
+    pub max_hp: [i8; 2],
+    pub round: i32,
+    // The loser of a lethal bout doesn't walk away
+    pub lethal: bool,
+    // Which side, if any, has begged for mercy
+    pub yielded: Option<usize>,
+    // How much the crowd is with each side
+    pub favour: [i32; 2],
+    // AC bonus or penalty for this round only
+    guard: [i8; 2],
+    // Carried to each side's next attack, from aiming or being taunted
+    hit_bonus: [i8; 2],
+    // The active defence each side is ready with this round, if any
+    defence: [Option<Action>; 2],
+    conditions: [Vec<Affliction>; 2],
+    // Whether each owner wants luck burned this round, or None to leave it
+    // to the gladiator's judgement
+    pub luck_orders: [Option<bool>; 2],
+    pub luck_burned: [i8; 2],
+    pub fatigue: [i8; 2],
+    // Damage each side has dealt, for the judges
+    pub damage: [i32; 2],
+    // The side the judges gave the bout to when it went the distance
+    pub decision: Option<usize>,
+    // Injuries each side picked up this bout
+    pub injuries: [Vec<Injury>; 2],
+    // How the fallen side met their end, for the obituary
+    pub cause_of_death: Option<String>,
+}
+
+impl Bout {
+    pub fn new(glad1: Character, glad2: Character) -> Bout {
+        let mut gladiators = [glad1, glad2];
+
+        // Roll for initiative, highest swings first
+        for glad in gladiators.iter_mut() {
+            glad.initiative = roller(1, 20) + calc_modifier(glad.agility);
+        }
+        if gladiators[1].initiative > gladiators[0].initiative {
+            gladiators.swap(0, 1);
+        }
+
+        // The Andabatae fight in visored helmets without eyeholes
+        let conditions = [0, 1].map(|side| match gladiators[side].style.as_str() {
+            "Andabatae" => vec![Affliction {
+                condition: Condition::Blinded,
+                rounds: None,
+            }],
+            _ => Vec::new(),
+        });
+
+        // Wounds not yet healed carry into the bout
+        let max_hp = [gladiators[0].hp, gladiators[1].hp];
+        for glad in gladiators.iter_mut() {
+            glad.hp = (glad.hp - glad.wounds).max(1);
+        }
+
+        Bout {
+            max_hp,
+            gladiators,
+            round: 0,
+            lethal: false,
+            yielded: None,
+            favour: [0, 0],
+            guard: [0, 0],
+            hit_bonus: [0, 0],
+            defence: [None, None],
+            conditions,
+            luck_orders: [None, None],
+            luck_burned: [0, 0],
+            fatigue: [0, 0],
+            damage: [0, 0],
+            decision: None,
+            injuries: [Vec::new(), Vec::new()],
+            cause_of_death: None,
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.yielded.is_some()
+            || self.decision.is_some()
+            || self.gladiators.iter().any(|glad| glad.hp <= 0)
+    }
+
+    pub fn winner(&self) -> Option<&Character> {
+        if !self.is_over() {
+            return None;
+        }
+        match (self.yielded, self.decision) {
+            (Some(side), _) => Some(&self.gladiators[1 - side]),
+            (None, Some(side)) => Some(&self.gladiators[side]),
+            (None, None) => self.gladiators.iter().find(|glad| glad.hp > 0),
+        }
+    }
+
+    pub fn loser(&self) -> Option<&Character> {
+        match (self.yielded, self.decision) {
+            (Some(side), _) => Some(&self.gladiators[side]),
+            (None, Some(side)) => Some(&self.gladiators[1 - side]),
+            (None, None) => self.gladiators.iter().find(|glad| glad.hp <= 0),
+        }
+    }
+
+    // What a gladiator does when nobody tells them otherwise, by their profile
+    // and how the fight is going
+    pub fn ai_action(&self, side: usize) -> Action {
+        let glad = &self.gladiators[side];
+        let health = glad.hp as i32 * 100 / self.max_hp[side].max(1) as i32;
+        let opponent_health =
+            self.gladiators[1 - side].hp as i32 * 100 / self.max_hp[1 - side].max(1) as i32;
+        let roll = roller(1, 100) as i32;
+
+        match glad.profile {
+            Profile::Aggressive if roll <= 35 => Action::MightyDeed,
+            Profile::Aggressive => Action::Attack,
+            Profile::Defensive if health <= 25 && roll <= 20 => Action::Yield,
+            Profile::Defensive if health <= 50 && roll <= 50 => best_defence(glad),
+            Profile::Defensive if roll <= 20 => Action::Defend,
+            Profile::Defensive => Action::Attack,
+            Profile::Opportunist if health <= 20 && roll <= 10 => Action::Yield,
+            Profile::Opportunist if self.hit_bonus[side] > 0 || opponent_health <= 30 => {
+                Action::MightyDeed
+            }
+            Profile::Opportunist if roll <= 35 => Action::Aim,
+            Profile::Opportunist => Action::Attack,
+            Profile::Showboat if health > 50 && roll <= 35 => Action::Taunt,
+            Profile::Showboat if roll <= 20 => Action::MightyDeed,
+            Profile::Showboat => Action::Attack,
+        }
+    }
+
+    pub fn play_round(&mut self) -> Vec<String> {
+        let actions = [self.ai_action(0), self.ai_action(1)];
+        self.play_round_with(actions)
+    }
+
+    // Plays a round with each side's chosen action, in initiative order
+    pub fn play_round_with(&mut self, actions: [Action; 2]) -> Vec<String> {
+        self.round += 1;
+        let mut log = Vec::new();
+
+        // Nobody swings at a gladiator who has thrown down their weapon
+        if let Some(side) = actions.iter().position(|action| *action == Action::Yield) {
+            self.yielded = Some(side);
+            log.push(format!(
+                "{} throws down their weapon and begs the crowd for mercy!",
+                self.gladiators[side].name
+            ));
+            return log;
+        }
+
+        // Guards go up, or down, before the first blow lands
+        for (side, action) in actions.iter().enumerate() {
+            self.guard[side] = match action {
+                Action::Defend => GUARD_BONUS,
+                Action::MightyDeed if is_martial(&self.gladiators[side].style) => -DEED_EXPOSURE,
+                _ => 0,
+            };
+            self.defence[side] = match action {
+                Action::Block | Action::Parry | Action::Dodge => {
+                    Some(usable_defence(&self.gladiators[side], *action))
+                }
+                _ => None,
+            };
+        }
+
+        for (side, action) in actions.iter().enumerate() {
+            let hp_before = self.gladiators[side].hp;
+            let can_act = self.start_turn(side, &mut log);
+            self.damage[1 - side] += (hp_before - self.gladiators[side].hp) as i32;
+            if self.gladiators[side].hp <= 0 {
+                log.push(format!(
+                    "{} has been defeated in mortal combat!",
+                    self.gladiators[side].name
+                ));
+                self.cause_of_death = Some(format!(
+                    "succumbed to their wounds facing {} in round {}",
+                    self.gladiators[1 - side].name,
+                    self.round
+                ));
+                break;
+            }
+            if !can_act {
+                continue;
+            }
+
+            let hp_before = self.gladiators[1 - side].hp;
+            log.push(self.act(side, *action));
+            self.damage[side] += (hp_before - self.gladiators[1 - side].hp) as i32;
+            let opponent = &self.gladiators[1 - side];
+            if opponent.hp <= 0 {
+                log.push(format!(
+                    "{} has been defeated in mortal combat!",
+                    opponent.name
+                ));
+                self.cause_of_death = Some(format!(
+                    "fell to {}'s {} in round {}",
+                    self.gladiators[side].name, self.gladiators[side].weapon.name, self.round
+                ));
+                break;
+            }
+        }
+
+        if !self.is_over() {
+            self.tire(&mut log);
+        }
+        if !self.is_over() && self.round >= MAX_ROUNDS {
+            self.judge(&mut log);
+        }
+
+        self.luck_orders = [None, None];
+        log
+    }
+
+    pub fn fatigue_penalty(&self, side: usize) -> i8 {
+        self.fatigue[side] / FATIGUE_STEP
+    }
+
+    // The longer the bout runs the harder it is to keep going, though a
+    // hardy gladiator holds out longer
+    fn tire(&mut self, log: &mut Vec<String>) {
+        let dc = FATIGUE_DC + (self.round / FATIGUE_RAMP) as i8;
+        for side in 0..2 {
+            if save(&self.gladiators[side], Save::Fortitude, dc) {
+                continue;
+            }
+            let penalty = self.fatigue_penalty(side);
+            self.fatigue[side] += 1;
+            if self.fatigue_penalty(side) > penalty {
+                log.push(format!(
+                    "{} is tiring, their guard dropping",
+                    self.gladiators[side].name
+                ));
+            }
+        }
+    }
+
+    // The judges weigh the blood drawn and the crowd's favour, then what's
+    // left of each gladiator, and failing that toss a coin
+    fn judge(&mut self, log: &mut Vec<String>) {
+        let score = |side: usize| {
+            (
+                self.damage[side] + self.favour[side],
+                self.gladiators[side].hp as i32 * 100 / self.max_hp[side].max(1) as i32,
+            )
+        };
+        let side = match score(0).cmp(&score(1)) {
+            Ordering::Greater => 0,
+            Ordering::Less => 1,
+            Ordering::Equal => (roller(1, 2) - 1) as usize,
+        };
+        self.decision = Some(side);
+        log.push(format!(
+            "The judges stop the bout after {} rounds and award it to {}!",
+            self.round, self.gladiators[side].name
+        ));
+    }
+
+    pub fn has(&self, side: usize, condition: Condition) -> bool {
+        self.conditions[side]
+            .iter()
+            .any(|affliction| affliction.condition == condition)
+    }
+
+    // The conditions a side is suffering, for showing alongside their HP
+    pub fn condition_names(&self, side: usize) -> Vec<&'static str> {
+        self.conditions[side]
+            .iter()
+            .map(|affliction| affliction.condition.as_str())
+            .collect()
+    }
+
+    // Lays a condition on a side unless they already suffer it
+    fn afflict(&mut self, side: usize, condition: Condition, rounds: Option<i8>) {
+        if !self.has(side, condition) {
+            self.conditions[side].push(Affliction { condition, rounds });
+        }
+    }
+
+    // Conditions take their toll at the start of each gladiator's turn,
+    // returning whether they're still able to act
+    fn start_turn(&mut self, side: usize, log: &mut Vec<String>) -> bool {
+        let mut can_act = true;
+        let mut afflictions = std::mem::take(&mut self.conditions[side]);
+        let glad = &mut self.gladiators[side];
+
+        afflictions.retain_mut(|affliction| {
+            let cured = match affliction.condition {
+                Condition::Bleeding => {
+                    glad.hp -= 1;
+                    let stanched = save(glad, Save::Fortitude, SAVE_DC);
+                    match stanched {
+                        true => {
+                            log.push(format!("{} bleeds [1] but stanches the wound", glad.name))
+                        }
+                        false => log.push(format!("{} loses blood [1]", glad.name)),
+                    }
+                    stanched
+                }
+                Condition::Stunned => {
+                    can_act = false;
+                    log.push(format!("{} reels, too stunned to act", glad.name));
+                    false
+                }
+                Condition::Entangled => {
+                    can_act = false;
+                    let freed = save(glad, Save::Reflex, SAVE_DC);
+                    match freed {
+                        true => log.push(format!("{} tears free!", glad.name)),
+                        false => log.push(format!("{} struggles, still entangled", glad.name)),
+                    }
+                    freed
+                }
+                Condition::Prone => {
+                    can_act = false;
+                    log.push(format!("{} scrambles back to their feet", glad.name));
+                    true
+                }
+                Condition::Blinded => false,
+                Condition::Poisoned => {
+                    let resisted = save(glad, Save::Fortitude, SAVE_DC);
+                    match resisted {
+                        true => log.push(format!("{} fights off the venom", glad.name)),
+                        false => {
+                            glad.hp -= 1;
+                            log.push(format!("{} retches as the poison bites [1]", glad.name));
+                        }
+                    }
+                    resisted
+                }
+            };
+
+            if let Some(rounds) = affliction.rounds.as_mut() {
+                *rounds -= 1;
+            }
+            !cured && affliction.rounds.is_none_or(|rounds| rounds > 0)
+        });
+
+        self.conditions[side] = afflictions;
+        can_act
+    }
+
+    // To-hit adjustment for one side attacking the other from their conditions
+    // and how tired each of them is
+    fn condition_modifier(&self, side: usize) -> i8 {
+        let mut modifier = 0;
+        if self.has(side, Condition::Blinded) {
+            modifier -= BLIND_PENALTY;
+        }
+        let exposed = [Condition::Prone, Condition::Entangled, Condition::Blinded];
+        if exposed
+            .iter()
+            .any(|condition| self.has(1 - side, *condition))
+        {
+            modifier += EXPOSED_BONUS;
+        }
+        modifier - self.fatigue_penalty(side) + self.fatigue_penalty(1 - side)
+    }
+
+    // How much luck a side would burn to land their next blow. Left to
+    // themselves they only burn it when the fight hangs on the blow
+    fn luck_to_burn(&self, side: usize) -> i8 {
+        let glad = &self.gladiators[side];
+        let opponent = &self.gladiators[1 - side];
+        let critical =
+            opponent.hp <= glad.weapon.damage_die || glad.hp as i32 * 4 <= self.max_hp[side] as i32;
+
+        match self.luck_orders[side].unwrap_or(critical) {
+            true => (glad.luck - MIN_LUCK).clamp(0, MAX_LUCK_BURN),
+            false => 0,
+        }
+    }
+
+    fn act(&mut self, side: usize, action: Action) -> String {
+        let luck = self.luck_to_burn(side);
+        let modifier = self.condition_modifier(side);
+        let (first, second) = self.gladiators.split_at_mut(1);
+        let (attacker, opponent) = match side {
+            0 => (&mut first[0], &mut second[0]),
+            _ => (&mut second[0], &mut first[0]),
+        };
+        let luck_before = attacker.luck;
+        let opponent_hp = opponent.hp;
+
+        let mut inflicted = Vec::new();
+        let out = match action {
+            // Every attack a trained fighter makes adds the deed die to the
+            // attack and damage. A mighty deed declares a deed as well, which
+            // comes off if the blow lands with a high enough roll
+            Action::Attack | Action::MightyDeed => {
+                let mut to_hit = self.hit_bonus[side] - self.guard[1 - side] + modifier;
+                if attacker.proficiencies.contains(&attacker.weapon.name) {
+                    to_hit += PROFICIENCY_BONUS;
+                }
+                let deed_roll = match (is_martial(&attacker.style), deed_die(attacker.level)) {
+                    (true, Some(die)) => roller(1, die),
+                    _ => 0,
+                };
+                to_hit += deed_roll;
+                let deed = match action == Action::MightyDeed && is_martial(&attacker.style) {
+                    true => Some(declare_deed(attacker, opponent)),
+                    false => None,
+                };
+                self.hit_bonus[side] = 0;
+
+                let (hit, landed, crit) = attack(
+                    attacker,
+                    opponent,
+                    to_hit,
+                    deed_roll,
+                    luck,
+                    self.defence[1 - side],
+                );
+                self.luck_burned[side] += luck_before - attacker.luck;
+                let severe = (opponent_hp - opponent.hp) * 2 >= self.max_hp[1 - side];
+                let mut out = match (action, deed) {
+                    (_, Some(deed)) => {
+                        format!("{} attempts a {}! {}", attacker.name, deed.as_str(), hit)
+                    }
+                    (Action::MightyDeed, None) => {
+                        format!("{} throws caution to the wind! {}", attacker.name, hit)
+                    }
+                    _ => hit,
+                };
+
+                if crit && opponent.hp > 0 {
+                    if let Some((condition, rounds)) = critical_condition(attacker, opponent) {
+                        append(
+                            &mut out,
+                            &format!("{} is {}!", opponent.name, condition.as_str()),
+                        );
+                        inflicted.push((condition, rounds));
+                    }
+                }
+                if landed && (crit || severe) && !save(opponent, Save::Fortitude, INJURY_DC) {
+                    let had = [&opponent.injuries, &self.injuries[1 - side]];
+                    let fresh: Vec<_> = Injury::ALL
+                        .iter()
+                        .filter(|injury| !had.iter().any(|had| had.contains(injury)))
+                        .collect();
+                    if let Some(injury) = fresh.choose(&mut rand::thread_rng()) {
+                        append(&mut out, &injury.narration(&opponent.name));
+                        self.injuries[1 - side].push(**injury);
+                    }
+                }
+                // Being knocked down, netted or blinded can be avoided with a
+                // quick enough reflex
+                if let (Some(deed), true) = (deed, landed && deed_roll >= DEED_SUCCESS) {
+                    let dc = DEED_SAVE_DC + deed_roll;
+                    let resisted = matches!(deed, Deed::Trip | Deed::Blind)
+                        && save(opponent, Save::Reflex, dc);
+                    match resisted {
+                        true => append(&mut out, &deed_resisted(deed, attacker, opponent)),
+                        false => append(&mut out, &deed_narration(deed, attacker, opponent)),
+                    }
+                    match deed {
+                        _ if resisted => {}
+                        Deed::Disarm => opponent.weapon = get_weapon("Fists".to_string()),
+                        Deed::Trip if entangles(&attacker.style) => {
+                            inflicted.push((Condition::Entangled, None))
+                        }
+                        Deed::Trip => inflicted.push((Condition::Prone, Some(1))),
+                        Deed::Blind => inflicted.push((Condition::Blinded, Some(BLINDED_ROUNDS))),
+                        Deed::Push => {
+                            self.guard[1 - side] -= PUSH_ADVANTAGE;
+                            self.hit_bonus[side] += PUSH_ADVANTAGE;
+                        }
+                    }
+                }
+                out
+            }
+            Action::Defend => format!("{} raises their guard", attacker.name),
+            Action::Block | Action::Parry | Action::Dodge => match (action, self.defence[side]) {
+                (Action::Block, Some(Action::Block)) => {
+                    format!("{} sets their shield and waits", attacker.name)
+                }
+                (Action::Parry, Some(Action::Parry)) => format!(
+                    "{} holds their {} ready to parry",
+                    attacker.name, attacker.weapon.name
+                ),
+                (Action::Dodge, _) => format!("{} stays light on their feet", attacker.name),
+                (Action::Block, _) => format!(
+                    "{} has no shield to hide behind and readies to fend off the blow instead",
+                    attacker.name
+                ),
+                _ => format!(
+                    "{} has nothing to parry with and readies to dodge instead",
+                    attacker.name
+                ),
+            },
+            Action::Aim => {
+                self.hit_bonus[side] += AIM_BONUS;
+                format!("{} circles, looking for an opening", attacker.name)
+            }
+            Action::Taunt => {
+                let dc = TAUNT_DC + calc_modifier(attacker.personality);
+                if !save(opponent, Save::Willpower, dc) {
+                    self.hit_bonus[1 - side] -= TAUNT_PENALTY;
+                    self.favour[side] += 1;
+                    format!(
+                        "{} taunts {} into a blind rage, and the crowd roars!",
+                        attacker.name, opponent.name
+                    )
+                } else {
+                    format!(
+                        "{} taunts {}, who doesn't rise to it",
+                        attacker.name, opponent.name
+                    )
+                }
+            }
+            Action::Yield => format!("{} yields", attacker.name),
+        };
+
+        for (condition, rounds) in inflicted {
+            self.afflict(1 - side, condition, rounds);
+        }
+        out
+    }
+}
+
+// Everything that follows from a decided bout, however it was arranged
+pub fn record(bout: &Bout) -> Result<()> {
+    let (winner, loser) = match (bout.winner(), bout.loser()) {
+        (Some(winner), Some(loser)) => (winner, loser),
+        _ => return Ok(()),
+    };
+    rating::update(winner, loser)?;
+    let side = match bout.gladiators[0].id == winner.id {
+        true => 0,
+        false => 1,
+    };
+
+    // Beating a stablemate earns no purse and no fame, like the owner
+    // ratings it proves nothing
+    let db = open_db()?;
+    if winner.owner_id.is_none() || winner.owner_id != loser.owner_id {
+        ludus::record_win(winner.guild_id, winner.owner_id, bout.favour[side])?;
+        db.execute(
+            "UPDATE glads SET fame = fame + ?1 WHERE id = ?2",
+            params![ludus::win_fame(bout.favour[side]), winner.id],
+        )?;
+    }
+    retirement::check_rudis(winner.id)?;
+    advance(winner.id)?;
+    // The best kill is the highest rated gladiator they put in the ground
+    if bout.lethal && loser.hp <= 0 {
+        db.execute(
+            "UPDATE glads SET kills = kills + 1,
+            best_kill_id = CASE WHEN best_kill_rating IS NULL OR ?1 > best_kill_rating THEN ?2 ELSE best_kill_id END,
+            best_kill_rating = MAX(COALESCE(best_kill_rating, ?1), ?1)
+            WHERE id = ?3",
+            params![loser.rating, loser.id, winner.id],
+        )?;
+        db.execute(
+            "UPDATE glads SET slain_by_id = ?1, cause_of_death = ?2 WHERE id = ?3",
+            params![winner.id, bout.cause_of_death, loser.id],
+        )?;
+    }
+    for (i, glad) in bout.gladiators.iter().enumerate() {
+        let opponent = &bout.gladiators[1 - i];
+        let taken = (bout.max_hp[i] - glad.hp.max(0)).max(0) as i64;
+        let recovery = RECOVERY_HOURS * 3600 * taken / bout.max_hp[i].max(1) as i64;
+        let dead = bout.lethal && glad.hp <= 0;
+
+        db.execute(
+            "UPDATE glads SET last_opponent_id = ?1, wounded_until = ?2, dead = ?3,
+            luck = luck - ?4, luck_burned = luck_burned + ?4, wounds = ?5, rested_since = ?6
+            WHERE id = ?7",
+            params![
+                opponent.id,
+                now() + recovery,
+                dead,
+                bout.luck_burned[i],
+                taken,
+                now(),
+                glad.id
+            ],
+        )?;
+        for injury in bout.injuries[i].iter() {
+            db.execute(
+                "UPDATE glads SET injuries = CASE WHEN injuries = '' THEN ?1 ELSE injuries || '; ' || ?1 END WHERE id = ?2",
+                params![injury.as_str(), glad.id],
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+// Raises a winner's level once they have enough wins for it
+fn advance(glad_id: i64) -> Result<()> {
+    let glad = get_character(glad_id)?;
+    let (wins, _) = rating::get_record(glad_id)?;
+    let level = (1 + wins / WINS_PER_LEVEL).min(MAX_LEVEL as i64) as i8;
+    if level <= glad.level {
+        return Ok(());
+    }
+
+    let hp: i8 = (glad.level..level)
+        .map(|_| (roller(1, LEVEL_HIT_DIE) + calc_modifier(glad.stamina)).max(1))
+        .sum();
+    let db = open_db()?;
+    db.execute(
+        "UPDATE glads SET level = ?1, hp = hp + ?2 WHERE id = ?3",
+        params![level, hp, glad_id],
+    )?;
+
+    Ok(())
+}
+
+// Fights a bout to the end without narrating it
+pub fn resolve(glad1: Character, glad2: Character) -> Bout {
+    let mut bout = Bout::new(glad1, glad2);
+    while !bout.is_over() {
+        bout.play_round();
+    }
+    bout
+}
+
+impl Deed {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Deed::Disarm => "disarm",
+            Deed::Trip => "trip",
+            Deed::Blind => "blinding strike",
+            Deed::Push => "push",
+        }
+    }
+}
+
+// DCC warriors start with a d3 deed die and it grows a step each level.
+// Peasants who haven't reached first level have none
+fn deed_die(level: i8) -> Option<i8> {
+    match level {
+        1.. => Some((level + 2).min(DEED_DIE_MAX)),
+        _ => None,
+    }
+}
+
+// The blinded and the runaways never learned the deeds of the arena
+fn is_martial(style: &str) -> bool {
+    !matches!(style, "Andabatae" | "Fugitivus")
+}
+
+// The deeds each style is drilled in, one picked at the moment
+fn declare_deed(attacker: &Character, opponent: &Character) -> Deed {
+    let deeds: &[Deed] = match attacker.style.as_str() {
+        "Retiarius" | "Laquearius" => &[Deed::Trip, Deed::Disarm],
+        "Pugilatus" => &[Deed::Blind, Deed::Push],
+        "Sagittarius" => &[Deed::Blind],
+        "Dimachaerus" | "Scissor" => &[Deed::Disarm, Deed::Blind],
+        "Bestiarius" | "Cataphractarius" | "Essedarius" => &[Deed::Trip, Deed::Push],
+        style if shield_bonus(style) > 0 => &[Deed::Push, Deed::Disarm],
+        _ => &[Deed::Disarm, Deed::Trip, Deed::Blind, Deed::Push],
+    };
+
+    // There's no disarming someone already fighting with their fists
+    let deeds: Vec<Deed> = deeds
+        .iter()
+        .filter(|deed| **deed != Deed::Disarm || opponent.weapon.name != "Fists")
+        .copied()
+        .collect();
+    *deeds.choose(&mut rand::thread_rng()).unwrap_or(&Deed::Push)
+}
+
+fn deed_resisted(deed: Deed, attacker: &Character, opponent: &Character) -> String {
+    let o = &opponent.name;
+    match deed {
+        Deed::Trip if entangles(&attacker.style) => {
+            format!("{} twists away before they're caught!", o)
+        }
+        Deed::Trip => format!("{} stumbles but keeps their feet!", o),
+        Deed::Blind => format!("{} turns their face away just in time!", o),
+        _ => format!("{} shrugs it off!", o),
+    }
+}
+
+fn deed_narration(deed: Deed, attacker: &Character, opponent: &Character) -> String {
+    let (a, o) = (&attacker.name, &opponent.name);
+    match (deed, attacker.style.as_str()) {
+        (Deed::Disarm, "Retiarius") => format!(
+            "{} hooks {}'s {} with the trident and flings it away!",
+            a, o, opponent.weapon.name
+        ),
+        (Deed::Disarm, _) => format!(
+            "{} sends {}'s {} spinning across the sand!",
+            a, o, opponent.weapon.name
+        ),
+        (Deed::Trip, "Retiarius") => format!("{} casts the net over {} and drags them down!", a, o),
+        (Deed::Trip, "Laquearius") => {
+            format!("{}'s lasso snares {}'s legs and pulls them down!", a, o)
+        }
+        (Deed::Trip, _) => format!("{} sweeps {}'s legs out from under them!", a, o),
+        (Deed::Blind, "Pugilatus") => format!("{} jabs at {}'s eyes, leaving them reeling!", a, o),
+        (Deed::Blind, "Sagittarius") => format!(
+            "{}'s arrow grazes {}'s brow and blood runs into their eyes!",
+            a, o
+        ),
+        (Deed::Blind, _) => format!("{} flings a fistful of sand into {}'s eyes!", a, o),
+        (Deed::Push, style) if shield_bonus(style) > 0 => {
+            format!("{} slams their shield into {}, driving them back!", a, o)
+        }
+        (Deed::Push, _) => format!("{} shoulders {} back against the wall!", a, o),
+    }
+}
+
+// Adds a sentence to the narration, finishing off the last one if need be
+fn append(out: &mut String, sentence: &str) {
+    match out.ends_with('!') || out.ends_with('.') {
+        true => out.push(' '),
+        false => out.push_str(". "),
+    }
+    out.push_str(sentence);
+}
+
+// Nets and lassos bind rather than knock down
+fn entangles(style: &str) -> bool {
+    matches!(style, "Retiarius" | "Laquearius")
+}
+
+fn is_blunt(weapon: &str) -> bool {
+    matches!(weapon, "Fists" | "Club" | "Cestus" | "Warhammer")
+}
+
+fn save(glad: &Character, save: Save, dc: i8) -> bool {
+    roller(1, 20) + glad.save_bonus(save) >= dc
+}
+
+// A critical hit from an edge leaves a bleeding wound, one from something
+// blunt can leave the victim stunned and a poisoned arrow can sicken them,
+// if they don't shrug it off
+fn critical_condition(
+    attacker: &Character,
+    opponent: &Character,
+) -> Option<(Condition, Option<i8>)> {
+    if attacker.style == "Sagittarius" {
+        return match save(opponent, Save::Fortitude, SAVE_DC) {
+            true => None,
+            false => Some((Condition::Poisoned, Some(POISON_ROUNDS))),
+        };
+    }
+    match is_blunt(&attacker.weapon.name) {
+        true if save(opponent, Save::Fortitude, SAVE_DC) => None,
+        true => Some((Condition::Stunned, Some(1))),
+        false => Some((Condition::Bleeding, None)),
+    }
+}
+
+// The defence a gladiator would choose when told to defend themselves
+fn best_defence(glad: &Character) -> Action {
+    if shield_bonus(&glad.style) > 0 {
+        Action::Block
+    } else if glad.weapon.is_melee {
+        Action::Parry
+    } else {
+        Action::Dodge
+    }
+}
+
+// Falls back to what the gladiator can actually do: no shield, no block,
+// and nothing but fists or a bow can't parry
+fn usable_defence(glad: &Character, defence: Action) -> Action {
+    match defence {
+        Action::Block if shield_bonus(&glad.style) == 0 => usable_defence(glad, Action::Parry),
+        Action::Parry if !glad.weapon.is_melee || glad.weapon.name == "Fists" => Action::Dodge,
+        defence => defence,
+    }
+}
+
+// An opposed roll to turn aside a blow that would otherwise land
+fn react(
+    attacker: &Character,
+    defender: &Character,
+    defence: Action,
+    to_hit: i8,
+) -> Option<String> {
+    let roll = roller(1, 20);
+    match defence {
+        Action::Block
+            if roll + calc_modifier(defender.strength) + 2 * shield_bonus(&defender.style)
+                >= to_hit =>
+        {
+            Some(format!(
+                "{} catches {}'s {} on their shield with a dull thud",
+                defender.name, attacker.name, attacker.weapon.name
+            ))
+        }
+        // Arrows and javelins can't be parried, only avoided
+        Action::Parry
+            if attacker.weapon.is_melee && roll + calc_modifier(defender.strength) >= to_hit =>
+        {
+            Some(format!(
+                "{} turns aside {}'s {} with their own {}",
+                defender.name, attacker.name, attacker.weapon.name, defender.weapon.name
+            ))
+        }
+        Action::Dodge if roll + calc_modifier(defender.agility) >= to_hit => Some(format!(
+            "{} twists out of the way of {}'s {}",
+            defender.name, attacker.name, attacker.weapon.name
+        )),
+        _ => None,
+    }
+}
+
+// Burns up to `luck` points of the attacker's luck if that's enough to turn
+// a miss into a hit
+fn attack(
+    attacker: &mut Character,
+    opponent: &mut Character,
+    to_hit_bonus: i8,
+    dmg_bonus: i8,
+    luck: i8,
+    defence: Option<Action>,
+) -> (String, bool, bool) {
+    let mut attack_modifier = calc_modifier(attacker.strength);
+    let mut dmg_modifier = calc_modifier(attacker.strength);
+    if !attacker.weapon.is_melee {
+        attack_modifier = calc_modifier(attacker.agility);
+        dmg_modifier = 0;
+    }
+
+    let roll = roller(1, 20);
+    let mut to_hit = roll + attack_modifier + to_hit_bonus;
+    let mut burned = String::new();
+    if to_hit < opponent.ac && opponent.ac - to_hit <= luck {
+        let burn = opponent.ac - to_hit;
+        attacker.luck -= burn;
+        to_hit += burn;
+        burned = format!("{} burns {} luck! ", attacker.name, burn);
+    }
+
+    if to_hit >= opponent.ac {
+        if let Some(parried) =
+            defence.and_then(|defence| react(attacker, opponent, defence, to_hit))
+        {
+            return (burned + &parried, false, false);
+        }
+
+        // A hit always does at least a point, however weak the arm behind it
+        let dmg = (roller(1, attacker.weapon.damage_die) + dmg_modifier + dmg_bonus).max(1);
+        opponent.hp -= dmg;
+        let out = get_hit_msg(
+            attacker.weapon.name.clone(),
+            attacker.name.clone(),
+            opponent.name.clone(),
+            dmg,
+        );
+        (burned + &out, true, roll == 20)
+    } else {
+        (
+            format!("{} misses their attack", attacker.name),
+            false,
+            false,
+        )
+    }
+}
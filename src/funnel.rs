@@ -0,0 +1,216 @@
+use serenity::builder::{CreateApplicationCommands, CreateEmbed};
+use serenity::client::Context;
+use serenity::framework::standard::{
+    macros::{command, group},
+    CommandResult,
+};
+use serenity::model::channel::Message;
+use serenity::utils::Colour;
+
+use rusqlite::{params, Connection, OptionalExtension, Result};
+
+use crate::combat::{self, Profile};
+use crate::slash::Invocation;
+use crate::{
+    calc_modifier, gen_character, get_weapon, ludus, now, open_db, rating, roller, save_character,
+    Character,
+};
+
+// Each funnel throws this many peasants at the arena, once a day
+const RECRUITS: usize = 4;
+const FUNNEL_COOLDOWN: i64 = 24 * 3600;
+// The trials in order: who the recruits face, with what, and their HP and AC
+const TRIALS: [(&str, &str, i8, i8); 3] = [
+    ("Condemned Criminal", "Fists", 1, 8),
+    ("Arena Guard", "Club", 2, 9),
+    ("Aging Gladiator", "Dagger", 3, 10),
+];
+
+#[group]
+#[commands(funnel)]
+struct Funnels;
+
+fn open() -> Result<Connection> {
+    let db = open_db()?;
+
+    match db.execute_batch(
+        "
+    CREATE TABLE IF NOT EXISTS funnels (
+        guild_id INTEGER,
+        owner_id INTEGER,
+        run_at INTEGER,
+        PRIMARY KEY (guild_id, owner_id)
+    );",
+    ) {
+        Ok(result) => result,
+        Err(e) => {
+            println!("error creating funnel table: {}", e);
+            return Err(e);
+        }
+    };
+
+    Ok(db)
+}
+
+fn get_last_run(guild_id: u64, owner_id: u64) -> Result<i64> {
+    let db = open()?;
+
+    let run_at = db
+        .query_row(
+            "SELECT run_at FROM funnels WHERE guild_id = ?1 AND owner_id = ?2",
+            params![guild_id, owner_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    Ok(run_at.unwrap_or(0))
+}
+
+fn set_last_run(guild_id: u64, owner_id: u64) -> Result<()> {
+    let db = open()?;
+
+    db.execute(
+        "INSERT INTO funnels VALUES (?1, ?2, ?3)
+        ON CONFLICT (guild_id, owner_id) DO UPDATE SET run_at = ?3",
+        params![guild_id, owner_id, now()],
+    )?;
+
+    Ok(())
+}
+
+// A 0-level peasant has only a d4 of HP to their name, the rest comes
+// with surviving to level 1
+fn peasant(glad: &Character) -> Character {
+    let mut peasant = glad.clone();
+    peasant.level = 0;
+    peasant.hp = (roller(1, 4) + calc_modifier(peasant.stamina)).max(1);
+    peasant
+}
+
+fn foe(trial: &(&str, &str, i8, i8)) -> Character {
+    let (name, weapon, hp, ac) = *trial;
+    let mut foe = gen_character();
+    // Not a saved gladiator, so no id of their own
+    foe.id = -1;
+    foe.name = name.to_string();
+    // Runaways know no deeds, so the trials stay a test of the recruits
+    foe.style = "Fugitivus".to_string();
+    foe.weapon = get_weapon(weapon.to_string());
+    foe.hp = hp;
+    foe.ac = ac;
+    foe.level = 0;
+    foe.profile = Profile::Aggressive;
+    foe
+}
+
+// Runs a peasant through every trial in turn, wounds and all, returning how
+// they fell or None if they made it through
+fn run_gauntlet(glad: &Character) -> Option<String> {
+    let mut peasant = peasant(glad);
+    for (i, trial) in TRIALS.iter().enumerate() {
+        let bout = combat::resolve(peasant.clone(), foe(trial));
+        let side = match bout.gladiators[0].id == peasant.id {
+            true => 0,
+            false => 1,
+        };
+        let fallen = bout.gladiators[side].hp <= 0;
+        let lost = bout.winner().map(|winner| winner.id) != Some(peasant.id);
+        let fate = match (lost, fallen, bout.yielded) {
+            (false, _, _) => None,
+            (true, true, _) => Some("Slain"),
+            (true, false, Some(_)) => Some("Begged for mercy"),
+            (true, false, None) => Some("Bested"),
+        };
+        if let Some(fate) = fate {
+            return Some(format!("{} by the {} in trial {}", fate, trial.0, i + 1));
+        }
+        peasant.wounds = bout.max_hp[side] - bout.gladiators[side].hp;
+    }
+
+    None
+}
+
+pub fn register(commands: &mut CreateApplicationCommands) -> &mut CreateApplicationCommands {
+    commands.create_application_command(|command| {
+        command
+            .name("funnel")
+            .description("Throw four peasants at the arena's trials, survivors join your ludus")
+    })
+}
+
+pub async fn run(inv: &Invocation<'_>) -> CommandResult {
+    run_funnel(inv).await
+}
+
+#[command]
+async fn funnel(ctx: &Context, msg: &Message) -> CommandResult {
+    run_funnel(&Invocation::from_message(ctx, msg)).await
+}
+
+async fn run_funnel(inv: &Invocation<'_>) -> CommandResult {
+    let guild_id = match inv.guild_id {
+        Some(guild_id) => guild_id.0,
+        None => {
+            inv.reply("The funnel only runs in servers.").await?;
+            return Ok(());
+        }
+    };
+    let owner_id = inv.author.id.0;
+
+    let ready_at = get_last_run(guild_id, owner_id)? + FUNNEL_COOLDOWN;
+    if ready_at > now() {
+        let out = format!(
+            "The arena has no more peasants to spare today, try again in {} hours.",
+            (ready_at - now() + 3599) / 3600
+        );
+        inv.reply(&out).await?;
+        return Ok(());
+    }
+    set_last_run(guild_id, owner_id)?;
+
+    let trials: Vec<&str> = TRIALS.iter().map(|trial| trial.0).collect();
+    let mut embed = CreateEmbed::default();
+    embed.title("The Funnel").description(format!(
+        "{}'s {} peasants face the trials of the arena: {}.",
+        inv.author.name,
+        RECRUITS,
+        trials.join(", ")
+    ));
+
+    let mut survivors = 0;
+    for _ in 0..RECRUITS {
+        let mut glad = gen_character();
+        let fate = match run_gauntlet(&glad) {
+            Some(fate) => fate,
+            None if !ludus::has_room(guild_id, owner_id)? => {
+                "Survived, but there's no room in your ludus".to_string()
+            }
+            None => {
+                glad.owner_id = Some(owner_id);
+                glad.guild_id = Some(guild_id);
+                save_character(glad.clone())?;
+                survivors += 1;
+                format!("Survived! Joins your ludus with {} HP", glad.hp)
+            }
+        };
+        embed.field(format!("{} the {}", glad.name, glad.style), fate, false);
+    }
+    if survivors > 0 {
+        rating::register_owner(guild_id, owner_id, &inv.author.name)?;
+    }
+
+    embed
+        .colour(match survivors {
+            0 => Colour::DARK_RED,
+            _ => Colour::GOLD,
+        })
+        .footer(|footer| {
+            footer.text(format!(
+                "{} of {} survive to become gladiators",
+                survivors, RECRUITS
+            ))
+        });
+    inv.reply_embed(embed).await?;
+
+    Ok(())
+}
@@ -0,0 +1,200 @@
+use serenity::builder::{CreateApplicationCommands, CreateEmbed};
+use serenity::client::Context;
+use serenity::framework::standard::{
+    macros::{command, group},
+    Args, CommandResult,
+};
+use serenity::model::application::command::CommandOptionType;
+use serenity::model::application::interaction::application_command::CommandDataOption;
+use serenity::model::channel::Message;
+use serenity::utils::Colour;
+
+use rusqlite::{params, OptionalExtension, Result};
+
+use crate::combat::Bout;
+use crate::slash::{option_str, Invocation};
+use crate::{get_character, open_db, rating};
+
+const HALL_SIZE: i64 = 10;
+// What the hall can be ranked by, wins first
+const ORDERS: [&str; 3] = ["wins", "kills", "fame"];
+
+#[group]
+#[commands(hof)]
+struct Halls;
+
+struct Legend {
+    name: String,
+    style: String,
+    dead: bool,
+    retired: bool,
+    rudis: bool,
+    wins: i64,
+    kills: i64,
+    fame: i64,
+}
+
+// Everyone who has made a name for themselves, living, retired or dead
+fn get_legends(guild_id: u64, order: &str) -> Result<Vec<Legend>> {
+    let db = open_db()?;
+
+    let mut stmt = db.prepare(&format!(
+        "SELECT * FROM (
+            SELECT name, style, dead, retired, rudis, kills, fame,
+            (SELECT COUNT(*) FROM rating_history h
+            WHERE h.subject = 'glad' AND h.subject_id = g.id AND h.delta > 0) AS wins
+            FROM glads g WHERE guild_id = ?1
+        ) WHERE wins > 0 OR kills > 0 OR fame > 0
+        ORDER BY {} DESC, wins DESC LIMIT ?2",
+        order
+    ))?;
+    let rows = stmt.query_map(params![guild_id, HALL_SIZE], |row| {
+        Ok(Legend {
+            name: row.get(0)?,
+            style: row.get(1)?,
+            dead: row.get(2)?,
+            retired: row.get(3)?,
+            rudis: row.get(4)?,
+            kills: row.get(5)?,
+            fame: row.get(6)?,
+            wins: row.get(7)?,
+        })
+    })?;
+    rows.collect()
+}
+
+// The strongest gladiator they killed and how they themselves died
+fn get_death(glad_id: i64) -> Result<(Option<i64>, Option<String>)> {
+    let db = open_db()?;
+
+    db.query_row(
+        "SELECT best_kill_id, cause_of_death FROM glads WHERE id = ?1",
+        [glad_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+}
+
+// Written from the fallen gladiator's stored record once a lethal bout has
+// been recorded, or None if nobody died
+pub fn obituary(bout: &Bout) -> Result<Option<CreateEmbed>> {
+    let fallen = match bout.loser() {
+        Some(loser) if bout.lethal && loser.hp <= 0 => get_character(loser.id)?,
+        _ => return Ok(None),
+    };
+    let (wins, losses) = rating::get_record(fallen.id)?;
+    let (best_kill_id, cause) = get_death(fallen.id)?;
+    let best_kill = match best_kill_id {
+        Some(id) => get_character(id).optional()?,
+        None => None,
+    };
+
+    let mut out = format!(
+        "{} the {} {} {}.",
+        fallen.name,
+        fallen.nationality,
+        fallen.style,
+        cause.unwrap_or_else(|| "fell in the arena".to_string())
+    );
+    out.push_str(&format!(
+        " They leave a record of {} wins and {} losses.",
+        wins, losses
+    ));
+    out.push_str(&match (fallen.kills, best_kill) {
+        (0, _) => " They never took a life in the arena.".to_string(),
+        (kills, Some(best)) => format!(
+            " They sent {} to the underworld, the finest of them {} the {}.",
+            kills, best.name, best.style
+        ),
+        (kills, None) => format!(" They sent {} to the underworld.", kills),
+    });
+    if !fallen.titles.is_empty() {
+        out.push_str(&format!(" Remembered as {}.", fallen.titles));
+    }
+
+    let mut embed = CreateEmbed::default();
+    embed
+        .title(format!("In memoriam: {}", fallen.name))
+        .description(out)
+        .colour(Colour::DARK_GREY)
+        .footer(|footer| {
+            footer.text(format!(
+                "Level {}, rated {:.0}, fame {}",
+                fallen.level, fallen.rating, fallen.fame
+            ))
+        });
+
+    Ok(Some(embed))
+}
+
+pub fn register(commands: &mut CreateApplicationCommands) -> &mut CreateApplicationCommands {
+    commands.create_application_command(|command| {
+        command
+            .name("hof")
+            .description("The hall of fame, the legends of this server's arena")
+            .create_option(|option| {
+                option
+                    .name("by")
+                    .description("What to rank the legends by")
+                    .kind(CommandOptionType::String);
+                for order in ORDERS {
+                    option.add_string_choice(order, order);
+                }
+                option
+            })
+    })
+}
+
+pub async fn run(inv: &Invocation<'_>, options: &[CommandDataOption]) -> CommandResult {
+    show_hall(inv, option_str(options, "by").unwrap_or("")).await
+}
+
+#[command]
+async fn hof(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    show_hall(&Invocation::from_message(ctx, msg), args.rest()).await
+}
+
+async fn show_hall(inv: &Invocation<'_>, order: &str) -> CommandResult {
+    let guild_id = match inv.guild_id {
+        Some(guild_id) => guild_id.0,
+        None => {
+            inv.reply("The hall of fame is only kept in servers.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    // Only ever one of ORDERS goes into the query
+    let order = ORDERS
+        .iter()
+        .find(|known| order.trim().eq_ignore_ascii_case(known))
+        .unwrap_or(&ORDERS[0]);
+    let legends = get_legends(guild_id, order)?;
+    if legends.is_empty() {
+        inv.reply("No legends have been made in this arena yet.")
+            .await?;
+        return Ok(());
+    }
+
+    let mut out = format!("**Hall of Fame**, by {}\n", order);
+    for (i, legend) in legends.iter().enumerate() {
+        let status = match (legend.dead, legend.retired, legend.rudis) {
+            (true, _, _) => " ✝",
+            (false, true, true) => " (retired with the rudis)",
+            (false, true, false) => " (retired)",
+            _ => "",
+        };
+        out.push_str(&format!(
+            "{}. {} the {}{}: {} wins, {} kills, fame {}\n",
+            i + 1,
+            legend.name,
+            legend.style,
+            status,
+            legend.wins,
+            legend.kills,
+            legend.fame
+        ));
+    }
+    inv.reply(&out).await?;
+
+    Ok(())
+}
@@ -0,0 +1,616 @@
+use serenity::builder::CreateApplicationCommands;
+use serenity::client::Context;
+use serenity::framework::standard::{
+    macros::{command, group},
+    Args, CommandResult,
+};
+use serenity::model::application::command::CommandOptionType;
+use serenity::model::application::interaction::application_command::CommandDataOption;
+use serenity::model::channel::Message;
+use serenity::model::id::ChannelId;
+
+use rusqlite::{params, Connection, OptionalExtension, Result, Row};
+
+use crate::slash::{gladiator_option, option_int, option_str, subcommand, Invocation};
+use crate::{award_title, combat, find_character, get_character, in_guild, is_owner, now, open_db};
+
+const DEFAULT_MATCHDAY_HOURS: i64 = 24;
+const WIN_POINTS: i64 = 3;
+
+#[group]
+#[commands(league)]
+struct Leagues;
+
+#[derive(Clone, Debug)]
+struct Season {
+    id: i64,
+    channel_id: u64,
+    name: String,
+    status: String,
+    matchday: i64,
+    matchdays: i64,
+    matchday_hours: i64,
+}
+
+#[derive(Clone, Debug)]
+struct Standing {
+    glad_id: i64,
+    name: String,
+    played: i64,
+    wins: i64,
+    losses: i64,
+    points: i64,
+}
+
+fn open() -> Result<Connection> {
+    let db = open_db()?;
+
+    match db.execute_batch(
+        "
+    CREATE TABLE IF NOT EXISTS seasons (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        channel_id INTEGER,
+        name STRING,
+        status STRING,
+        matchday INTEGER,
+        matchdays INTEGER,
+        matchday_hours INTEGER,
+        next_matchday_at INTEGER,
+        champion_id INTEGER
+    );
+    CREATE TABLE IF NOT EXISTS season_entries (
+        season_id INTEGER,
+        glad_id INTEGER,
+        played INTEGER,
+        wins INTEGER,
+        losses INTEGER,
+        points INTEGER,
+        PRIMARY KEY (season_id, glad_id)
+    );
+    CREATE TABLE IF NOT EXISTS season_fixtures (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        season_id INTEGER,
+        matchday INTEGER,
+        glad1_id INTEGER,
+        glad2_id INTEGER,
+        winner_id INTEGER
+    );",
+    ) {
+        Ok(result) => result,
+        Err(e) => {
+            println!("error creating league tables: {}", e);
+            return Err(e);
+        }
+    };
+
+    Ok(db)
+}
+
+fn row_to_season(row: &Row) -> Result<Season> {
+    Ok(Season {
+        id: row.get(0)?,
+        channel_id: row.get(1)?,
+        name: row.get(2)?,
+        status: row.get(3)?,
+        matchday: row.get(4)?,
+        matchdays: row.get(5)?,
+        matchday_hours: row.get(6)?,
+    })
+}
+
+// Each channel runs at most one season at a time
+fn get_current(db: &Connection, channel_id: u64) -> Result<Option<Season>> {
+    db.query_row(
+        "SELECT id, channel_id, name, status, matchday, matchdays, matchday_hours FROM seasons
+        WHERE channel_id = ?1 AND status != 'finished' ORDER BY id DESC LIMIT 1",
+        [channel_id],
+        row_to_season,
+    )
+    .optional()
+}
+
+fn get_due(db: &Connection) -> Result<Vec<Season>> {
+    let mut stmt = db.prepare(
+        "SELECT id, channel_id, name, status, matchday, matchdays, matchday_hours FROM seasons
+        WHERE status = 'running' AND next_matchday_at <= ?1",
+    )?;
+    let rows = stmt.query_map([now()], row_to_season)?;
+    rows.collect()
+}
+
+fn get_standings(db: &Connection, season_id: i64) -> Result<Vec<Standing>> {
+    let mut stmt = db.prepare(
+        "SELECT e.glad_id, g.name, e.played, e.wins, e.losses, e.points FROM season_entries e
+        JOIN glads g ON g.id = e.glad_id
+        WHERE e.season_id = ?1 ORDER BY e.points DESC, e.losses ASC, e.glad_id ASC",
+    )?;
+    let rows = stmt.query_map([season_id], |row| {
+        Ok(Standing {
+            glad_id: row.get(0)?,
+            name: row.get(1)?,
+            played: row.get(2)?,
+            wins: row.get(3)?,
+            losses: row.get(4)?,
+            points: row.get(5)?,
+        })
+    })?;
+    rows.collect()
+}
+
+// Circle method: the first gladiator stays put while everyone else rotates
+// around them, so each pair meets exactly once. An odd field gets an empty
+// seat and whoever draws it rests that matchday.
+fn round_robin(glad_ids: &[i64]) -> Vec<Vec<(i64, i64)>> {
+    let mut seats: Vec<Option<i64>> = glad_ids.iter().map(|id| Some(*id)).collect();
+    if seats.len() % 2 == 1 {
+        seats.push(None);
+    }
+    let n = seats.len();
+
+    let mut matchdays = Vec::new();
+    for _ in 0..n - 1 {
+        let mut fixtures = Vec::new();
+        for i in 0..n / 2 {
+            if let (Some(glad1), Some(glad2)) = (seats[i], seats[n - 1 - i]) {
+                fixtures.push((glad1, glad2));
+            }
+        }
+        matchdays.push(fixtures);
+
+        let last = seats.pop().unwrap();
+        seats.insert(1, last);
+    }
+    matchdays
+}
+
+// Fights the next matchday's fixtures and returns the results and table to
+// post, closing the season out after the last one
+fn play_matchday(db: &Connection, season: &Season) -> Result<String> {
+    let matchday = season.matchday + 1;
+
+    let mut stmt = db.prepare(
+        "SELECT id, glad1_id, glad2_id FROM season_fixtures WHERE season_id = ?1 AND matchday = ?2",
+    )?;
+    let fixtures = stmt
+        .query_map(params![season.id, matchday], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?
+        .collect::<Result<Vec<(i64, i64, i64)>>>()?;
+
+    let mut out = format!(
+        "**{}**: Matchday {} of {}\n",
+        season.name, matchday, season.matchdays
+    );
+    for (fixture_id, glad1_id, glad2_id) in fixtures {
+        let glad1 = get_character(glad1_id)?;
+        let glad2 = get_character(glad2_id)?;
+
+        // The dead and the retired forfeit whatever is left of their season
+        let out1 = glad1.dead || glad1.retired;
+        let out2 = glad2.dead || glad2.retired;
+        let (winner_id, loser_id, result) = match (out1, out2) {
+            (true, true) => {
+                out.push_str(&format!(
+                    "{} and {} are both gone from the arena, the fixture is void\n",
+                    glad1.name, glad2.name
+                ));
+                continue;
+            }
+            (true, false) | (false, true) => {
+                let (winner, loser) = match out1 {
+                    true => (glad2, glad1),
+                    false => (glad1, glad2),
+                };
+                let result = format!(
+                    "{} wins by walkover, {} is {}\n",
+                    winner.name,
+                    loser.name,
+                    match loser.dead {
+                        true => "dead",
+                        false => "retired",
+                    }
+                );
+                (winner.id, loser.id, result)
+            }
+            (false, false) => {
+                let bout = combat::resolve(glad1, glad2);
+                combat::record(&bout)?;
+                let winner = bout.winner().unwrap();
+                let loser = bout.loser().unwrap();
+                let result = format!(
+                    "{} defeated {} after {} rounds\n",
+                    winner.name, loser.name, bout.round
+                );
+                (winner.id, loser.id, result)
+            }
+        };
+
+        db.execute(
+            "UPDATE season_fixtures SET winner_id = ?1 WHERE id = ?2",
+            params![winner_id, fixture_id],
+        )?;
+        db.execute(
+            "UPDATE season_entries SET played = played + 1, wins = wins + 1, points = points + ?1
+            WHERE season_id = ?2 AND glad_id = ?3",
+            params![WIN_POINTS, season.id, winner_id],
+        )?;
+        db.execute(
+            "UPDATE season_entries SET played = played + 1, losses = losses + 1
+            WHERE season_id = ?1 AND glad_id = ?2",
+            params![season.id, loser_id],
+        )?;
+        out.push_str(&result);
+    }
+
+    let standings = get_standings(db, season.id)?;
+    out.push('\n');
+    out.push_str(&standings_table(&standings));
+
+    if matchday >= season.matchdays {
+        let champion = &standings[0];
+        db.execute(
+            "UPDATE seasons SET matchday = ?1, status = 'finished', champion_id = ?2 WHERE id = ?3",
+            params![matchday, champion.glad_id, season.id],
+        )?;
+        award_title(champion.glad_id, &format!("Champion of {}", season.name))?;
+        out.push_str(&format!(
+            "\n{} is crowned Champion of {}!",
+            champion.name, season.name
+        ));
+    } else {
+        db.execute(
+            "UPDATE seasons SET matchday = ?1, next_matchday_at = ?2 WHERE id = ?3",
+            params![matchday, now() + season.matchday_hours * 3600, season.id],
+        )?;
+        out.push_str(&format!(
+            "\nNext matchday in {} hours.",
+            season.matchday_hours
+        ));
+    }
+
+    Ok(out)
+}
+
+fn standings_table(standings: &[Standing]) -> String {
+    let mut out = String::new();
+    for (i, standing) in standings.iter().enumerate() {
+        out.push_str(&format!(
+            "{}. {}: P{} W{} L{}, {} pts\n",
+            i + 1,
+            standing.name,
+            standing.played,
+            standing.wins,
+            standing.losses,
+            standing.points
+        ));
+    }
+    out
+}
+
+// Called by the scheduler to play any matchdays that have come due
+pub async fn tick(ctx: &Context) {
+    let db = match open() {
+        Ok(db) => db,
+        Err(e) => {
+            println!("error opening leagues: {}", e);
+            return;
+        }
+    };
+    let due = match get_due(&db) {
+        Ok(due) => due,
+        Err(e) => {
+            println!("error loading seasons: {}", e);
+            return;
+        }
+    };
+
+    for season in due {
+        match play_matchday(&db, &season) {
+            Ok(out) => {
+                if let Err(e) = ChannelId(season.channel_id).say(&ctx.http, &out).await {
+                    println!("error posting matchday: {}", e);
+                }
+            }
+            Err(e) => println!("error playing season {}: {}", season.id, e),
+        }
+    }
+}
+
+pub fn register(commands: &mut CreateApplicationCommands) -> &mut CreateApplicationCommands {
+    commands.create_application_command(|command| {
+        command
+            .name("league")
+            .description("Round-robin league seasons")
+            .create_option(|option| {
+                option
+                    .name("show")
+                    .description("Show the standings of this channel's season")
+                    .kind(CommandOptionType::SubCommand)
+            })
+            .create_option(|option| {
+                option
+                    .name("create")
+                    .description("Open registration for a season in this channel")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|option| {
+                        option
+                            .name("name")
+                            .description("What the league is called")
+                            .kind(CommandOptionType::String)
+                    })
+                    .create_sub_option(|option| {
+                        option
+                            .name("hours")
+                            .description("Hours between matchdays")
+                            .kind(CommandOptionType::Integer)
+                            .min_int_value(0)
+                    })
+            })
+            .create_option(|option| {
+                option
+                    .name("join")
+                    .description("Register a gladiator")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|option| {
+                        gladiator_option(option, "Gladiator to register", true)
+                    })
+            })
+            .create_option(|option| {
+                option
+                    .name("start")
+                    .description("Close registration and draw up the fixtures")
+                    .kind(CommandOptionType::SubCommand)
+            })
+    })
+}
+
+pub async fn run(inv: &Invocation<'_>, options: &[CommandDataOption]) -> CommandResult {
+    match subcommand(options) {
+        Some(("create", options)) => {
+            let matchday_hours = option_int(options, "hours").unwrap_or(DEFAULT_MATCHDAY_HOURS);
+            let name = option_str(options, "name").unwrap_or("");
+            create_league(inv, matchday_hours.max(0), name).await
+        }
+        Some(("join", options)) => {
+            join_league(inv, option_str(options, "gladiator").unwrap_or("")).await
+        }
+        Some(("start", _)) => start_league(inv).await,
+        _ => show_league(inv).await,
+    }
+}
+
+#[command]
+#[sub_commands(create, join, start)]
+async fn league(ctx: &Context, msg: &Message) -> CommandResult {
+    show_league(&Invocation::from_message(ctx, msg)).await
+}
+
+async fn show_league(inv: &Invocation<'_>) -> CommandResult {
+    let db = open()?;
+    let out = match get_current(&db, inv.channel_id.0)? {
+        Some(season) => {
+            let standings = get_standings(&db, season.id)?;
+            format!(
+                "**{}** ({}, matchday {} of {})\n{}",
+                season.name,
+                season.status,
+                season.matchday,
+                season.matchdays,
+                standings_table(&standings)
+            )
+        }
+        None => "There is no league season in this channel. Start one with `/league create [hours between matchdays] <name>`".to_string(),
+    };
+    inv.reply(&out).await?;
+
+    Ok(())
+}
+
+#[command]
+async fn create(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let mut matchday_hours = DEFAULT_MATCHDAY_HOURS;
+    let mut name = Vec::new();
+    for arg in args.raw() {
+        match arg.parse::<i64>() {
+            Ok(hours) if name.is_empty() => matchday_hours = hours.max(0),
+            _ => name.push(arg),
+        }
+    }
+
+    create_league(
+        &Invocation::from_message(ctx, msg),
+        matchday_hours,
+        &name.join(" "),
+    )
+    .await
+}
+
+async fn create_league(inv: &Invocation<'_>, matchday_hours: i64, name: &str) -> CommandResult {
+    let db = open()?;
+    if get_current(&db, inv.channel_id.0)?.is_some() {
+        inv.reply("A season is already under way in this channel!")
+            .await?;
+        return Ok(());
+    }
+
+    let name = match name {
+        "" => format!("The League of {}", inv.author.name),
+        name => name.to_string(),
+    };
+
+    db.execute(
+        "INSERT INTO seasons VALUES (NULL, ?1, ?2, 'open', 0, 0, ?3, NULL, NULL)",
+        params![inv.channel_id.0, name, matchday_hours],
+    )?;
+    println!("{} created season {}", inv.author.name, name);
+
+    let out = format!(
+        "{} has opened registration for **{}**! Enter your gladiators with `/league join <name>`",
+        inv.author.name, name
+    );
+    inv.reply(&out).await?;
+
+    Ok(())
+}
+
+#[command]
+async fn join(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    join_league(&Invocation::from_message(ctx, msg), args.rest()).await
+}
+
+async fn join_league(inv: &Invocation<'_>, name: &str) -> CommandResult {
+    let db = open()?;
+    let season = match get_current(&db, inv.channel_id.0)? {
+        Some(season) if season.status == "open" => season,
+        _ => {
+            inv.reply("There is no season taking entries in this channel.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let glad = match find_character(
+        name,
+        inv.guild_id.map(|guild_id| guild_id.0),
+        inv.author.id.0,
+    )? {
+        Some(glad) if !in_guild(&glad, inv.guild_id.map(|guild_id| guild_id.0)) => {
+            let out = format!("{} fights in another arena.", glad.name);
+            inv.reply(&out).await?;
+            return Ok(());
+        }
+        Some(glad) if !is_owner(&glad, inv.author.id.0) => {
+            let out = format!("{} does not fight for you!", glad.name);
+            inv.reply(&out).await?;
+            return Ok(());
+        }
+        Some(glad) if !glad.dead && !glad.retired => glad,
+        Some(glad) if glad.retired => {
+            let out = format!("{} has retired from the arena.", glad.name);
+            inv.reply(&out).await?;
+            return Ok(());
+        }
+        Some(glad) => {
+            let out = format!("{} is dead and cannot compete.", glad.name);
+            inv.reply(&out).await?;
+            return Ok(());
+        }
+        None => {
+            inv.reply("No gladiator goes by that name.").await?;
+            return Ok(());
+        }
+    };
+
+    let inserted = db.execute(
+        "INSERT OR IGNORE INTO season_entries VALUES (?1, ?2, 0, 0, 0, 0)",
+        params![season.id, glad.id],
+    )?;
+    let out = match inserted {
+        0 => format!("{} is already registered for {}", glad.name, season.name),
+        _ if !glad.titles.is_empty() => format!(
+            "{} ({}) registers for {}",
+            glad.name, glad.titles, season.name
+        ),
+        _ => format!("{} registers for {}", glad.name, season.name),
+    };
+    inv.reply(&out).await?;
+
+    Ok(())
+}
+
+#[command]
+async fn start(ctx: &Context, msg: &Message) -> CommandResult {
+    start_league(&Invocation::from_message(ctx, msg)).await
+}
+
+async fn start_league(inv: &Invocation<'_>) -> CommandResult {
+    let db = open()?;
+    let season = match get_current(&db, inv.channel_id.0)? {
+        Some(season) if season.status == "open" => season,
+        _ => {
+            inv.reply("There is no season waiting to start in this channel.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let glad_ids: Vec<i64> = get_standings(&db, season.id)?
+        .iter()
+        .map(|standing| standing.glad_id)
+        .collect();
+    if glad_ids.len() < 2 {
+        inv.reply("A league needs at least two gladiators!").await?;
+        return Ok(());
+    }
+
+    let schedule = round_robin(&glad_ids);
+    for (i, fixtures) in schedule.iter().enumerate() {
+        for (glad1_id, glad2_id) in fixtures {
+            db.execute(
+                "INSERT INTO season_fixtures VALUES (NULL, ?1, ?2, ?3, ?4, NULL)",
+                params![season.id, i as i64 + 1, glad1_id, glad2_id],
+            )?;
+        }
+    }
+
+    // The first matchday is fought as soon as the scheduler next looks
+    db.execute(
+        "UPDATE seasons SET status = 'running', matchdays = ?1, next_matchday_at = ?2 WHERE id = ?3",
+        params![schedule.len() as i64, now(), season.id],
+    )?;
+
+    let out = format!(
+        "**{}** begins! {} gladiators will fight over {} matchdays, one every {} hours.",
+        season.name,
+        glad_ids.len(),
+        schedule.len(),
+        season.matchday_hours
+    );
+    inv.reply(&out).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_robin_pairs_everyone_exactly_once() {
+        for count in 2..=9 {
+            let ids: Vec<i64> = (1..=count).collect();
+            let matchdays = round_robin(&ids);
+            assert_eq!(matchdays.len() as i64, count + count % 2 - 1);
+
+            let mut met: Vec<(i64, i64)> = matchdays
+                .iter()
+                .flatten()
+                .map(|(a, b)| (*a.min(b), *a.max(b)))
+                .collect();
+            met.sort();
+            let mut expected = Vec::new();
+            for a in 1..=count {
+                for b in a + 1..=count {
+                    expected.push((a, b));
+                }
+            }
+            assert_eq!(met, expected, "with {} gladiators", count);
+        }
+    }
+
+    #[test]
+    fn round_robin_fights_nobody_twice_in_a_matchday() {
+        for count in 2..=9 {
+            let ids: Vec<i64> = (1..=count).collect();
+            for fixtures in round_robin(&ids) {
+                let mut playing: Vec<i64> = fixtures.iter().flat_map(|(a, b)| [*a, *b]).collect();
+                let fixtures = playing.len();
+                playing.sort();
+                playing.dedup();
+                assert_eq!(playing.len(), fixtures);
+                // An odd field rests exactly one gladiator each matchday
+                assert_eq!(playing.len() as i64, count - count % 2);
+            }
+        }
+    }
+}
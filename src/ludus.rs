@@ -0,0 +1,450 @@
+use serenity::builder::{CreateApplicationCommands, CreateEmbed};
+use serenity::client::Context;
+use serenity::framework::standard::{
+    macros::{command, group},
+    Args, CommandResult,
+};
+use serenity::model::application::command::CommandOptionType;
+use serenity::model::application::interaction::application_command::CommandDataOption;
+use serenity::model::channel::Message;
+
+use rusqlite::{params, Connection, Result};
+
+use crate::slash::{option_str, subcommand, Invocation};
+use crate::{open_db, purse, retirement};
+
+// Every lanista starts with room for this many living gladiators
+const BASE_CAPACITY: i64 = 4;
+const BARRACKS_ROOM: i64 = 2;
+const MAX_CAPACITY: i64 = 12;
+const MAX_TRAINERS: i64 = 3;
+// Fame for each win, and again if the crowd was with the winner
+const FAME_PER_WIN: i64 = 1;
+// A win pays the lanista this much, plus a denarius for every few points of
+// the school's fame, up to a limit
+const WIN_PURSE: i64 = 10;
+const FAME_PER_DENARIUS: i64 = 5;
+const MAX_PURSE: i64 = 50;
+
+#[group]
+#[commands(ludus)]
+struct Ludi;
+
+pub struct Ludus {
+    pub name: String,
+    pub capacity: i64,
+    // Each doctore hired adds to every training roll, as does each retired
+    // gladiator who stayed on
+    pub trainers: i64,
+    pub veterans: i64,
+    // A valetudinarium speeds healing at rest
+    pub infirmary: bool,
+    pub fame: i64,
+}
+
+// What a lanista can spend their winnings on
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Facility {
+    Barracks,
+    Doctore,
+    Valetudinarium,
+}
+
+impl Facility {
+    const ALL: [Facility; 3] = [
+        Facility::Barracks,
+        Facility::Doctore,
+        Facility::Valetudinarium,
+    ];
+
+    fn parse(facility: &str) -> Option<Facility> {
+        let facility = facility.to_lowercase();
+        Facility::ALL
+            .iter()
+            .find(|candidate| candidate.as_str() == facility)
+            .copied()
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Facility::Barracks => "barracks",
+            Facility::Doctore => "doctore",
+            Facility::Valetudinarium => "valetudinarium",
+        }
+    }
+
+    fn cost(&self) -> i64 {
+        match self {
+            Facility::Barracks => 100,
+            Facility::Doctore => 150,
+            Facility::Valetudinarium => 200,
+        }
+    }
+}
+
+fn open() -> Result<Connection> {
+    let db = open_db()?;
+
+    match db.execute_batch(
+        "
+    CREATE TABLE IF NOT EXISTS ludi (
+        guild_id INTEGER,
+        owner_id INTEGER,
+        name STRING NOT NULL DEFAULT '',
+        capacity INTEGER,
+        trainers INTEGER NOT NULL DEFAULT 0,
+        infirmary INTEGER NOT NULL DEFAULT 0,
+        fame INTEGER NOT NULL DEFAULT 0,
+        PRIMARY KEY (guild_id, owner_id)
+    );",
+    ) {
+        Ok(result) => result,
+        Err(e) => {
+            println!("error creating ludus table: {}", e);
+            return Err(e);
+        }
+    };
+
+    Ok(db)
+}
+
+// Every player in a server has a ludus, whether they've named it or not
+pub fn get_ludus(guild_id: u64, owner_id: u64) -> Result<Ludus> {
+    let db = open()?;
+
+    db.execute(
+        "INSERT OR IGNORE INTO ludi (guild_id, owner_id, capacity) VALUES (?1, ?2, ?3)",
+        params![guild_id, owner_id, BASE_CAPACITY],
+    )?;
+    let veterans = retirement::count_trainers(guild_id, owner_id)?;
+    db.query_row(
+        "SELECT name, capacity, trainers, infirmary, fame FROM ludi
+        WHERE guild_id = ?1 AND owner_id = ?2",
+        params![guild_id, owner_id],
+        |row| {
+            Ok(Ludus {
+                name: row.get(0)?,
+                capacity: row.get(1)?,
+                trainers: row.get(2)?,
+                veterans,
+                infirmary: row.get(3)?,
+                fame: row.get(4)?,
+            })
+        },
+    )
+}
+
+// The ludus an owned gladiator trains in, if they have one
+pub fn get_home(guild_id: Option<u64>, owner_id: Option<u64>) -> Result<Option<Ludus>> {
+    match (guild_id, owner_id) {
+        (Some(guild_id), Some(owner_id)) => get_ludus(guild_id, owner_id).map(Some),
+        _ => Ok(None),
+    }
+}
+
+fn count_living(guild_id: u64, owner_id: u64) -> Result<i64> {
+    let db = open_db()?;
+
+    db.query_row(
+        "SELECT COUNT(*) FROM glads
+        WHERE guild_id = ?1 AND owner_id = ?2 AND dead = 0 AND retired = 0",
+        params![guild_id, owner_id],
+        |row| row.get(0),
+    )
+}
+
+pub fn has_room(guild_id: u64, owner_id: u64) -> Result<bool> {
+    Ok(count_living(guild_id, owner_id)? < get_ludus(guild_id, owner_id)?.capacity)
+}
+
+pub fn add_fame(guild_id: u64, owner_id: u64, fame: i64) -> Result<()> {
+    get_ludus(guild_id, owner_id)?;
+    let db = open()?;
+
+    db.execute(
+        "UPDATE ludi SET fame = fame + ?1 WHERE guild_id = ?2 AND owner_id = ?3",
+        params![fame, guild_id, owner_id],
+    )?;
+
+    Ok(())
+}
+
+// Fame for a win, more if the gladiator won the crowd
+pub fn win_fame(favour: i32) -> i64 {
+    let bonus = if favour > 0 { FAME_PER_WIN } else { 0 };
+    FAME_PER_WIN + bonus
+}
+
+fn win_purse(fame: i64) -> i64 {
+    (WIN_PURSE + fame / FAME_PER_DENARIUS).min(MAX_PURSE)
+}
+
+// Fame for a winning gladiator's school, and the arena's purse for their
+// lanista, the more famous the school the bigger
+pub fn record_win(guild_id: Option<u64>, owner_id: Option<u64>, favour: i32) -> Result<()> {
+    if let (Some(guild_id), Some(owner_id)) = (guild_id, owner_id) {
+        add_fame(guild_id, owner_id, win_fame(favour))?;
+        let fame = get_ludus(guild_id, owner_id)?.fame;
+        purse::credit(guild_id, owner_id, win_purse(fame))?;
+    }
+
+    Ok(())
+}
+
+// Wins, losses and deaths across everyone the school has sent out
+fn get_record(guild_id: u64, owner_id: u64) -> Result<(i64, i64, i64)> {
+    let db = open_db()?;
+
+    db.query_row(
+        "SELECT
+            (SELECT COUNT(*) FROM rating_history h JOIN glads g ON g.id = h.subject_id
+            WHERE h.subject = 'glad' AND g.guild_id = ?1 AND g.owner_id = ?2 AND h.delta > 0),
+            (SELECT COUNT(*) FROM rating_history h JOIN glads g ON g.id = h.subject_id
+            WHERE h.subject = 'glad' AND g.guild_id = ?1 AND g.owner_id = ?2 AND h.delta < 0),
+            (SELECT COUNT(*) FROM glads WHERE guild_id = ?1 AND owner_id = ?2 AND dead = 1)",
+        params![guild_id, owner_id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+    )
+}
+
+fn get_roster(guild_id: u64, owner_id: u64) -> Result<Vec<String>> {
+    let db = open_db()?;
+
+    let mut stmt = db.prepare(
+        "SELECT name FROM glads WHERE guild_id = ?1 AND owner_id = ?2 AND dead = 0 AND retired = 0
+        ORDER BY rating DESC",
+    )?;
+    let rows = stmt.query_map(params![guild_id, owner_id], |row| row.get(0))?;
+    rows.collect()
+}
+
+pub fn register(commands: &mut CreateApplicationCommands) -> &mut CreateApplicationCommands {
+    commands.create_application_command(|command| {
+        command
+            .name("ludus")
+            .description("Your gladiator school")
+            .create_option(|option| {
+                option
+                    .name("show")
+                    .description("Show your ludus")
+                    .kind(CommandOptionType::SubCommand)
+            })
+            .create_option(|option| {
+                option
+                    .name("rename")
+                    .description("Name your ludus")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|option| {
+                        option
+                            .name("name")
+                            .description("What the school is called")
+                            .kind(CommandOptionType::String)
+                            .required(true)
+                    })
+            })
+            .create_option(|option| {
+                option
+                    .name("buy")
+                    .description("Spend denarii on the school")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|option| {
+                        option
+                            .name("facility")
+                            .description("What to buy")
+                            .kind(CommandOptionType::String)
+                            .required(true);
+                        for facility in Facility::ALL {
+                            option.add_string_choice(
+                                format!("{} ({} denarii)", facility.as_str(), facility.cost()),
+                                facility.as_str(),
+                            );
+                        }
+                        option
+                    })
+            })
+    })
+}
+
+pub async fn run(inv: &Invocation<'_>, options: &[CommandDataOption]) -> CommandResult {
+    match subcommand(options) {
+        Some(("rename", options)) => {
+            rename_ludus(inv, option_str(options, "name").unwrap_or("")).await
+        }
+        Some(("buy", options)) => {
+            buy_facility(inv, option_str(options, "facility").unwrap_or("")).await
+        }
+        _ => show_ludus(inv).await,
+    }
+}
+
+#[command]
+#[sub_commands(rename, buy)]
+async fn ludus(ctx: &Context, msg: &Message) -> CommandResult {
+    show_ludus(&Invocation::from_message(ctx, msg)).await
+}
+
+async fn show_ludus(inv: &Invocation<'_>) -> CommandResult {
+    let guild_id = match inv.guild_id {
+        Some(guild_id) => guild_id.0,
+        None => {
+            inv.reply("Ludi are only kept in servers.").await?;
+            return Ok(());
+        }
+    };
+    let owner_id = inv.author.id.0;
+    let ludus = get_ludus(guild_id, owner_id)?;
+    let (wins, losses, deaths) = get_record(guild_id, owner_id)?;
+    let roster = get_roster(guild_id, owner_id)?;
+
+    let mut embed = CreateEmbed::default();
+    embed
+        .title(match ludus.name.as_str() {
+            "" => format!("The Ludus of {}", inv.author.name),
+            name => name.to_string(),
+        })
+        .field("Lanista", &inv.author.name, true)
+        .field(
+            "Gladiators",
+            format!("{} of {}", roster.len(), ludus.capacity),
+            true,
+        )
+        .field(
+            "Fame",
+            format!("{}, {} denarii a win", ludus.fame, win_purse(ludus.fame)),
+            true,
+        )
+        .field(
+            "Doctores",
+            match ludus.veterans {
+                0 => ludus.trainers.to_string(),
+                veterans => format!("{} and {} retired", ludus.trainers, veterans),
+            },
+            true,
+        )
+        .field(
+            "Valetudinarium",
+            match ludus.infirmary {
+                true => "yes",
+                false => "no",
+            },
+            true,
+        )
+        .field(
+            "Record",
+            format!("{} wins, {} losses, {} dead", wins, losses, deaths),
+            false,
+        );
+    if !roster.is_empty() {
+        embed.field("Roster", roster.join(", "), false);
+    }
+    inv.reply_embed(embed).await?;
+
+    Ok(())
+}
+
+#[command]
+async fn rename(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    rename_ludus(&Invocation::from_message(ctx, msg), args.rest()).await
+}
+
+async fn rename_ludus(inv: &Invocation<'_>, name: &str) -> CommandResult {
+    let guild_id = match inv.guild_id {
+        Some(guild_id) => guild_id.0,
+        None => {
+            inv.reply("Ludi are only kept in servers.").await?;
+            return Ok(());
+        }
+    };
+    let name = name.trim();
+    if name.is_empty() {
+        inv.reply("Name it what? `/ludus rename <name>`").await?;
+        return Ok(());
+    }
+
+    get_ludus(guild_id, inv.author.id.0)?;
+    let db = open()?;
+    db.execute(
+        "UPDATE ludi SET name = ?1 WHERE guild_id = ?2 AND owner_id = ?3",
+        params![name, guild_id, inv.author.id.0],
+    )?;
+    let out = format!("Your school shall be known as {}.", name);
+    inv.reply(&out).await?;
+
+    Ok(())
+}
+
+#[command]
+async fn buy(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    buy_facility(&Invocation::from_message(ctx, msg), args.rest()).await
+}
+
+async fn buy_facility(inv: &Invocation<'_>, facility: &str) -> CommandResult {
+    let guild_id = match inv.guild_id {
+        Some(guild_id) => guild_id.0,
+        None => {
+            inv.reply("Ludi are only kept in servers.").await?;
+            return Ok(());
+        }
+    };
+    let facility = match Facility::parse(facility.trim()) {
+        Some(facility) => facility,
+        None => {
+            let names: Vec<&str> = Facility::ALL
+                .iter()
+                .map(|facility| facility.as_str())
+                .collect();
+            let out = format!("Buy what? Choose one of: {}", names.join(", "));
+            inv.reply(&out).await?;
+            return Ok(());
+        }
+    };
+
+    let owner_id = inv.author.id.0;
+    let ludus = get_ludus(guild_id, owner_id)?;
+    let full = match facility {
+        Facility::Barracks => ludus.capacity >= MAX_CAPACITY,
+        Facility::Doctore => ludus.trainers >= MAX_TRAINERS,
+        Facility::Valetudinarium => ludus.infirmary,
+    };
+    if full {
+        let out = format!("Your ludus can't take any more {}.", facility.as_str());
+        inv.reply(&out).await?;
+        return Ok(());
+    }
+    if !purse::debit(guild_id, owner_id, facility.cost())? {
+        let out = format!(
+            "A {} costs {} denarii, which you don't have.",
+            facility.as_str(),
+            facility.cost()
+        );
+        inv.reply(&out).await?;
+        return Ok(());
+    }
+
+    let db = open()?;
+    let update = match facility {
+        Facility::Barracks => "capacity = capacity + ?1",
+        Facility::Doctore => "trainers = trainers + 1",
+        Facility::Valetudinarium => "infirmary = 1",
+    };
+    db.execute(
+        &format!(
+            "UPDATE ludi SET {} WHERE guild_id = ?2 AND owner_id = ?3",
+            update
+        ),
+        params![BARRACKS_ROOM, guild_id, owner_id],
+    )?;
+    let out = match facility {
+        Facility::Barracks => format!(
+            "New barracks go up, room for {} more gladiators.",
+            BARRACKS_ROOM
+        ),
+        Facility::Doctore => "A doctore joins your ludus to drill your gladiators.".to_string(),
+        Facility::Valetudinarium => {
+            "Your ludus opens a valetudinarium, your wounded will heal faster.".to_string()
+        }
+    };
+    inv.reply(&out).await?;
+
+    Ok(())
+}
@@ -1,11 +1,32 @@
+mod challenge;
+mod combat;
+mod funnel;
+mod hall;
+mod league;
+mod ludus;
+mod market;
+mod matchmaking;
+mod medicus;
+mod purse;
+mod rating;
+mod retirement;
+mod slash;
+mod tournament;
+mod training;
+
 use serenity::async_trait;
+use serenity::builder::{CreateComponents, CreateEmbed};
 use serenity::client::{Client, Context, EventHandler};
 use serenity::framework::standard::{
     macros::{command, group},
-    CommandResult, StandardFramework,
+    Args, CommandResult, StandardFramework,
 };
+use serenity::model::application::component::ButtonStyle;
+use serenity::model::application::interaction::{Interaction, InteractionResponseType};
 use serenity::model::channel::Message;
+use serenity::model::gateway::Ready;
 use serenity::prelude::*;
+use serenity::utils::Colour;
 
 use handlebars::Handlebars;
 use std::collections::HashMap;
@@ -13,21 +34,109 @@ use std::collections::HashMap;
 use rand::seq::SliceRandom;
 use rand::Rng;
 use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use rnglib::{Language, RNG};
-use rusqlite::{params, Connection, Result};
+use rusqlite::{params, Connection, OptionalExtension, Result, Row};
+
+use combat::{Action, Bout, Injury, Profile, Save};
+use slash::Invocation;
+
+const DB_PATH: &str = "/tmp/glad.db";
+const GLAD_COLUMNS: &str = "id, name, nationality, style, hp, ac, strength, agility, stamina, personality, inteligence, luck, notes, initiative, weapon_key, titles, owner_id, guild_id, rating, level, dead, wounded_until, last_opponent_id, profile, luck_burned, injuries, wounds, proficiencies, retired, rudis, kills, fame";
+
+// Columns added to glads since it was first created, applied to older databases on open
+const GLAD_MIGRATIONS: [&str; 25] = [
+    "titles STRING NOT NULL DEFAULT ''",
+    "owner_id INTEGER",
+    "guild_id INTEGER",
+    "rating REAL NOT NULL DEFAULT 1500",
+    "level INTEGER NOT NULL DEFAULT 1",
+    "dead INTEGER NOT NULL DEFAULT 0",
+    "wounded_until INTEGER NOT NULL DEFAULT 0",
+    "last_opponent_id INTEGER",
+    "profile STRING",
+    "luck_burned INTEGER NOT NULL DEFAULT 0",
+    "injuries STRING NOT NULL DEFAULT ''",
+    "wounds INTEGER NOT NULL DEFAULT 0",
+    "rested_since INTEGER NOT NULL DEFAULT 0",
+    "trained_at INTEGER NOT NULL DEFAULT 0",
+    "proficiencies STRING NOT NULL DEFAULT ''",
+    "retired INTEGER NOT NULL DEFAULT 0",
+    "retired_at INTEGER",
+    "rudis INTEGER NOT NULL DEFAULT 0",
+    "trainer INTEGER NOT NULL DEFAULT 0",
+    "kills INTEGER NOT NULL DEFAULT 0",
+    "fame INTEGER NOT NULL DEFAULT 0",
+    "best_kill_id INTEGER",
+    "best_kill_rating REAL",
+    "slain_by_id INTEGER",
+    "cause_of_death STRING",
+];
+
+const DEFAULT_PREFIX: &str = "!";
+
+// Seconds between rounds of a narrated fight
+const DEFAULT_FIGHT_PACE: u64 = 2;
+const HP_BAR_WIDTH: usize = 10;
+// Discord's limit on thread names
+const THREAD_NAME_LENGTH: usize = 100;
+const THREAD_ARCHIVE_MINUTES: u16 = 60;
+// How long owners get to choose an action in an interactive fight
+const TURN_SECONDS: u64 = 30;
+const BURN_LUCK: &str = "burn";
+// Discord fits at most five buttons in a row
+const BUTTONS_PER_ROW: usize = 5;
+
+static SCHEDULER_STARTED: AtomicBool = AtomicBool::new(false);
 
 #[group]
-#[commands(glad, taunt, fight)]
+#[commands(glad, sheet, profile, taunt, fight)]
 
 struct General;
 struct Handler;
 
 #[async_trait]
-impl EventHandler for Handler {}
+impl EventHandler for Handler {
+    async fn ready(&self, ctx: Context, ready: Ready) {
+        println!("{} is connected!", ready.user.name);
+
+        // Ready fires again on reconnect, only ever run one scheduler
+        if !SCHEDULER_STARTED.swap(true, Ordering::SeqCst) {
+            slash::register(&ctx).await;
+            tokio::spawn(scheduler(ctx));
+        }
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        slash::handle(&ctx, interaction).await;
+    }
+}
+
+// Runs anything that has come due, picking up where we left off after a restart
+async fn scheduler(ctx: Context) {
+    let mut interval = tokio::time::interval(Duration::from_secs(60));
+    loop {
+        interval.tick().await;
+        tournament::tick(&ctx).await;
+        league::tick(&ctx).await;
+        challenge::tick(&ctx).await;
+        medicus::tick(&ctx).await;
+        market::tick(&ctx).await;
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
 
 #[derive(Clone, Debug)]
 struct Character {
+    id: i64,
     name: String,
     nationality: String,
     style: String,
@@ -42,6 +151,38 @@ struct Character {
     notes: String,
     initiative: i8,
     weapon: Weapon,
+    titles: String,
+    owner_id: Option<u64>,
+    guild_id: Option<u64>,
+    rating: f64,
+    level: i8,
+    dead: bool,
+    wounded_until: i64,
+    last_opponent_id: Option<i64>,
+    profile: Profile,
+    luck_burned: i8,
+    injuries: Vec<Injury>,
+    // HP lost in the arena and not yet healed
+    wounds: i8,
+    // Weapons trained with, by name
+    proficiencies: Vec<String>,
+    retired: bool,
+    // Granted their freedom with the wooden sword
+    rudis: bool,
+    kills: i64,
+    fame: i64,
+}
+
+impl Character {
+    // Saves improve by the stat's modifier and a point per level
+    fn save_bonus(&self, save: Save) -> i8 {
+        let stat = match save {
+            Save::Fortitude => self.stamina,
+            Save::Reflex => self.agility,
+            Save::Willpower => self.personality,
+        };
+        calc_modifier(stat) + self.level
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -53,7 +194,6 @@ struct Weapon {
 
 fn get_weapon(weapon_key: String) -> Weapon {
     let mut weapon_table = HashMap::new();
-    println!("{}", weapon_key);
 
     weapon_table.insert(
         "Fists".to_string(),
@@ -63,6 +203,14 @@ fn get_weapon(weapon_key: String) -> Weapon {
             is_melee: true,
         },
     );
+    weapon_table.insert(
+        "Club".to_string(),
+        Weapon {
+            name: "Club".to_string(),
+            damage_die: 4,
+            is_melee: true,
+        },
+    );
     weapon_table.insert(
         "Sica".to_string(),
         Weapon {
@@ -237,7 +385,7 @@ fn calc_hp(stamina: i8, luck: i8, nationality: String) -> i8 {
         _ => roller(2, 4) + calc_modifier(stamina),
     };
 
-    hp
+    hp.max(1)
 }
 
 fn calc_ac(agility: i8, style: &str) -> i8 {
@@ -268,9 +416,16 @@ fn calc_ac(agility: i8, style: &str) -> i8 {
     ac
 }
 
-fn load_weapon(style: &str) -> Weapon {
-    let weapon: Weapon;
+// What the style's shield adds to AC, matching calc_ac, or 0 without one
+fn shield_bonus(style: &str) -> i8 {
+    match style {
+        "Velites" | "Hoplomachus" | "Eques" | "Thracian" => 1,
+        "Murmillo" | "Provacator" | "Samnite" => 2,
+        _ => 0,
+    }
+}
 
+fn load_weapon(style: &str) -> Weapon {
     match style {
         "Andabatae" => get_weapon("Short Sword".to_string()),
         "Fugitivus" => {
@@ -295,49 +450,21 @@ fn load_weapon(style: &str) -> Weapon {
             let choice = possible_weapons.choose(&mut rand::thread_rng()).unwrap();
             get_weapon(choice.to_string())
         }
-        _ => panic!("Invalid style"),
-    };
-
-    match style {
-        "Andabatae" => weapon = get_weapon("Short Sword".to_string()),
-        "Fugitivus" => {
-            let possible_weapons = [
-                "Fists",
-                "Club",
-                "Dagger",
-                "Short Sword",
-                "Hand Axe",
-                "Spear",
-                "Warhammer",
-                "Long Sword",
-            ];
-            let choice = possible_weapons.choose(&mut rand::thread_rng()).unwrap();
-            weapon = get_weapon(choice.to_string());
-        }
-        "Pugilatus" => weapon = get_weapon("Cestus".to_string()),
-        "Bestiarius" => weapon = get_weapon("Hand Axe".to_string()),
-        "Velites" => weapon = get_weapon("Javelin".to_string()),
-        "Thracian" => {
-            let possible_weapons = ["Dagger", "Sica", "Short Sword"];
-            let choice = possible_weapons.choose(&mut rand::thread_rng()).unwrap();
-            weapon = get_weapon(choice.to_string());
-        }
-        "Hoplomachus" => weapon = get_weapon("Spear".to_string()),
-        "Retiarius" => weapon = get_weapon("Trident".to_string()),
-        "Murmillo" => weapon = get_weapon("Short Sword".to_string()),
-        "Dimachaerus" => weapon = get_weapon("Long Sword".to_string()),
-        "Provacator" => weapon = get_weapon("Short Sword".to_string()),
-        "Laquearius" => weapon = get_weapon("Dagger".to_string()),
-        "Scissor" => weapon = get_weapon("Short Sword".to_string()),
-        "Samnite" => weapon = get_weapon("Short Sword".to_string()),
-        "Cataphractarius" => weapon = get_weapon("Polearm".to_string()),
+        "Hoplomachus" => get_weapon("Spear".to_string()),
+        "Retiarius" => get_weapon("Trident".to_string()),
+        "Murmillo" => get_weapon("Short Sword".to_string()),
+        "Dimachaerus" => get_weapon("Long Sword".to_string()),
+        "Provacator" => get_weapon("Short Sword".to_string()),
+        "Laquearius" => get_weapon("Dagger".to_string()),
+        "Scissor" => get_weapon("Short Sword".to_string()),
+        "Samnite" => get_weapon("Short Sword".to_string()),
+        "Cataphractarius" => get_weapon("Polearm".to_string()),
         // "Rudiarius" => notes = "2d100 GP starting funds for initial weapons/armor".to_string(),
-        "Sagittarius" => weapon = get_weapon("Shortbow".to_string()),
-        "Eques" => weapon = get_weapon("Javelin".to_string()),
-        "Essedarius" => weapon = get_weapon("Spear".to_string()),
-        _ => weapon = get_weapon("Fists".to_string()),
-    };
-    weapon
+        "Sagittarius" => get_weapon("Shortbow".to_string()),
+        "Eques" => get_weapon("Javelin".to_string()),
+        "Essedarius" => get_weapon("Spear".to_string()),
+        _ => get_weapon("Fists".to_string()),
+    }
 }
 
 fn load_notes(style: &str) -> String {
@@ -394,45 +521,13 @@ fn find_style(luck: i8) -> String {
     if roll < 0 {
         roll = 0;
     }
-    let style = styles.get(roll as usize);
+    let style = styles.get((roll as usize).min(styles.len() - 1));
 
     (*style.unwrap()).to_string()
 }
 
-fn get_characters(num: i8) -> Result<Vec<Character>> {
-    let db = Connection::open("/tmp/glad.db")?;
-
-    let mut stmt = db.prepare("SELECT * FROM glads ORDER BY id DESC LIMIT ?1")?;
-    let rows = stmt.query_map([num], |row| {
-        Ok(Character {
-            name: row.get(1).unwrap(),
-            nationality: row.get(2).unwrap(),
-            style: row.get(3).unwrap(),
-            hp: row.get(4).unwrap(),
-            ac: row.get(5).unwrap(),
-            strength: row.get(6).unwrap(),
-            agility: row.get(7).unwrap(),
-            stamina: row.get(8).unwrap(),
-            personality: row.get(9).unwrap(),
-            inteligence: row.get(10).unwrap(),
-            luck: row.get(11).unwrap(),
-            notes: row.get(12).unwrap(),
-            initiative: row.get(13).unwrap(),
-            weapon: get_weapon(row.get(14).unwrap()),
-        })
-    })?;
-
-    let mut characters = Vec::new();
-    for row in rows {
-        let character = row.unwrap();
-        characters.push(character);
-    }
-
-    Ok(characters)
-}
-
-fn save_character(character: Character) -> Result<()> {
-    let db = Connection::open("/tmp/glad.db")?;
+fn open_db() -> Result<Connection> {
+    let db = Connection::open(DB_PATH)?;
 
     match db.execute_batch(
         "
@@ -461,8 +556,111 @@ fn save_character(character: Character) -> Result<()> {
         }
     };
 
+    let existing = db
+        .prepare("SELECT name FROM pragma_table_info('glads')")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<Vec<String>>>()?;
+    for column in GLAD_MIGRATIONS.iter() {
+        let name = column.split(' ').next().unwrap();
+        if !existing.iter().any(|e| e == name) {
+            db.execute(&format!("ALTER TABLE glads ADD COLUMN {}", column), [])?;
+        }
+    }
+
+    Ok(db)
+}
+
+fn row_to_character(row: &Row) -> Result<Character> {
+    // Gladiators fight as their style taught them unless told otherwise
+    let style: String = row.get(3)?;
+    let profile = row
+        .get::<_, Option<String>>(23)?
+        .and_then(|profile| Profile::parse(&profile))
+        .unwrap_or_else(|| Profile::for_style(&style));
+
+    let mut glad = Character {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        nationality: row.get(2)?,
+        style,
+        hp: row.get(4)?,
+        ac: row.get(5)?,
+        strength: row.get(6)?,
+        agility: row.get(7)?,
+        stamina: row.get(8)?,
+        personality: row.get(9)?,
+        inteligence: row.get(10)?,
+        luck: row.get(11)?,
+        notes: row.get(12)?,
+        initiative: row.get(13)?,
+        weapon: get_weapon(row.get(14)?),
+        titles: row.get(15)?,
+        owner_id: row.get(16)?,
+        guild_id: row.get(17)?,
+        rating: row.get(18)?,
+        level: row.get(19)?,
+        dead: row.get(20)?,
+        wounded_until: row.get(21)?,
+        last_opponent_id: row.get(22)?,
+        profile,
+        luck_burned: row.get(24)?,
+        injuries: combat::parse_injuries(&row.get::<_, String>(25)?),
+        wounds: row.get(26)?,
+        proficiencies: row
+            .get::<_, String>(27)?
+            .split("; ")
+            .filter(|weapon| !weapon.is_empty())
+            .map(|weapon| weapon.to_string())
+            .collect(),
+        retired: row.get(28)?,
+        rudis: row.get(29)?,
+        kills: row.get(30)?,
+        fame: row.get(31)?,
+    };
+    // Stats are stored whole and injuries taken off as they're loaded, with
+    // AC following whatever agility is left
+    for injury in glad.injuries.clone() {
+        injury.apply(&mut glad);
+    }
+    glad.ac = calc_ac(glad.agility, &glad.style);
+
+    Ok(glad)
+}
+
+fn get_character(id: i64) -> Result<Character> {
+    let db = open_db()?;
+
+    db.query_row(
+        &format!("SELECT {} FROM glads WHERE id = ?1", GLAD_COLUMNS),
+        [id],
+        row_to_character,
+    )
+}
+
+// Names aren't unique, so prefer whoever fights in this server and then the
+// caller's own, the same way autocomplete offers them
+fn find_character(name: &str, guild_id: Option<u64>, user_id: u64) -> Result<Option<Character>> {
+    let db = open_db()?;
+
+    db.query_row(
+        &format!(
+            "SELECT {} FROM glads
+            WHERE name = ?1 COLLATE NOCASE AND (?2 IS NULL OR guild_id IS NULL OR guild_id = ?2)
+            ORDER BY owner_id IS ?3 DESC, id DESC LIMIT 1",
+            GLAD_COLUMNS
+        ),
+        params![name.trim(), guild_id, user_id],
+        row_to_character,
+    )
+    .optional()
+}
+
+fn save_character(character: Character) -> Result<i64> {
+    let db = open_db()?;
+
     let _result = match db.execute(
-        "INSERT INTO glads VALUES (NULL, ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+        "INSERT INTO glads (name, nationality, style, hp, ac, strength, agility, stamina, personality, inteligence, luck, notes, initiative, weapon_key, owner_id, guild_id, rating, level)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)",
         params![
             character.name,
             character.nationality,
@@ -477,7 +675,11 @@ fn save_character(character: Character) -> Result<()> {
             character.luck,
             character.notes,
             character.initiative,
-            character.weapon.name
+            character.weapon.name,
+            character.owner_id,
+            character.guild_id,
+            character.rating,
+            character.level
         ],
     ) {
         Ok(result) => result,
@@ -487,6 +689,31 @@ fn save_character(character: Character) -> Result<()> {
         }
     };
 
+    Ok(db.last_insert_rowid())
+}
+
+// Gladiators from before owners were tracked belong to everyone
+fn is_owner(glad: &Character, user_id: u64) -> bool {
+    match glad.owner_id {
+        Some(owner_id) => owner_id == user_id,
+        None => true,
+    }
+}
+
+// Gladiators belong to the server they were made in, unless they predate
+// servers being tracked
+fn in_guild(glad: &Character, guild_id: Option<u64>) -> bool {
+    glad.guild_id.is_none() || glad.guild_id == guild_id
+}
+
+fn award_title(glad_id: i64, title: &str) -> Result<()> {
+    let db = open_db()?;
+
+    db.execute(
+        "UPDATE glads SET titles = CASE WHEN titles = '' THEN ?1 ELSE titles || '; ' || ?1 END WHERE id = ?2",
+        params![title, glad_id],
+    )?;
+
     Ok(())
 }
 
@@ -525,8 +752,10 @@ fn gen_character() -> Character {
     let notes = load_notes(&style);
     let initiative = 0;
     let weapon = load_weapon(&style);
+    let profile = Profile::for_style(&style);
 
     Character {
+        id: 0,
         name: name.to_string(),
         nationality: (*nationality).to_string(),
         style,
@@ -541,23 +770,61 @@ fn gen_character() -> Character {
         notes,
         initiative,
         weapon,
+        titles: String::new(),
+        owner_id: None,
+        guild_id: None,
+        rating: rating::START_RATING,
+        level: 1,
+        dead: false,
+        wounded_until: 0,
+        last_opponent_id: None,
+        profile,
+        luck_burned: 0,
+        injuries: Vec::new(),
+        wounds: 0,
+        proficiencies: Vec::new(),
+        retired: false,
+        rudis: false,
+        kills: 0,
+        fame: 0,
     }
 }
 
 #[tokio::main]
 async fn main() {
-    let framework = StandardFramework::new()
-        .configure(|c| c.prefix("!")) // set the bot's prefix to "~"
-        .group(&GENERAL_GROUP);
-
     // Login with a bot token from the environment
     let token = env::var("GLADBOT_TOKEN").expect("token");
-    let intents = GatewayIntents::non_privileged() | GatewayIntents::MESSAGE_CONTENT;
-    let mut client = Client::builder(token, intents)
-        .event_handler(Handler)
-        .framework(framework)
-        .await
-        .expect("Error creating client");
+
+    // Prefix commands need the privileged message content intent. They
+    // answer to "!" unless GLADBOT_PREFIX says otherwise, and setting it
+    // empty leaves only slash commands
+    let prefix = env::var("GLADBOT_PREFIX").unwrap_or_else(|_| DEFAULT_PREFIX.to_string());
+    let prefix = Some(prefix).filter(|prefix| !prefix.is_empty());
+    let mut intents = GatewayIntents::non_privileged();
+    if prefix.is_some() {
+        intents |= GatewayIntents::MESSAGE_CONTENT;
+    }
+
+    let mut builder = Client::builder(token, intents).event_handler(Handler);
+    if let Some(prefix) = prefix {
+        let framework = StandardFramework::new()
+            .configure(|c| c.prefix(prefix))
+            .group(&GENERAL_GROUP)
+            .group(&tournament::TOURNAMENTS_GROUP)
+            .group(&league::LEAGUES_GROUP)
+            .group(&rating::RATINGS_GROUP)
+            .group(&purse::PURSES_GROUP)
+            .group(&challenge::CHALLENGES_GROUP)
+            .group(&medicus::MEDICI_GROUP)
+            .group(&training::TRAINING_GROUP)
+            .group(&ludus::LUDI_GROUP)
+            .group(&market::MARKETS_GROUP)
+            .group(&funnel::FUNNELS_GROUP)
+            .group(&retirement::RETIREMENTS_GROUP)
+            .group(&hall::HALLS_GROUP);
+        builder = builder.framework(framework);
+    }
+    let mut client = builder.await.expect("Error creating client");
 
     // start listening for events by starting a single shard
     if let Err(why) = client.start().await {
@@ -567,129 +834,538 @@ async fn main() {
 
 #[command]
 async fn glad(ctx: &Context, msg: &Message) -> CommandResult {
-    println!("{} asked me to create a new gladiator!", msg.author.name);
+    create_glad(&Invocation::from_message(ctx, msg)).await
+}
 
-    let glad = gen_character();
-    let strength_mod = calc_modifier(glad.strength);
-    let agility_mod = calc_modifier(glad.agility);
-    let stamina_mod = calc_modifier(glad.stamina);
-    let personality_mod = calc_modifier(glad.personality);
-    let inteligence_mod = calc_modifier(glad.inteligence);
-    let luck_mod = calc_modifier(glad.luck);
+async fn create_glad(inv: &Invocation<'_>) -> CommandResult {
+    println!("{} asked me to create a new gladiator!", inv.author.name);
+
+    if market::is_open() {
+        inv.reply("New gladiators are bought at the market, see `/market`.")
+            .await?;
+        return Ok(());
+    }
+    if let Some(guild_id) = inv.guild_id {
+        if !ludus::has_room(guild_id.0, inv.author.id.0)? {
+            inv.reply("Your ludus is full! Build more barracks with `/ludus buy barracks`.")
+                .await?;
+            return Ok(());
+        }
+    }
 
+    let mut glad = gen_character();
+    glad.owner_id = Some(inv.author.id.0);
+    glad.guild_id = inv.guild_id.map(|guild_id| guild_id.0);
     save_character(glad.clone())?;
+    if let Some(guild_id) = glad.guild_id {
+        rating::register_owner(guild_id, inv.author.id.0, &inv.author.name)?;
+    }
+    println!("{} has entered the arena!", glad.name);
+
+    let mut sheet = character_sheet(&glad)?;
+    sheet.description(format!("{} has entered the arena!", glad.name));
+    inv.reply_embed(sheet).await?;
+
+    Ok(())
+}
 
-    let out = format! {"Gladiator {} has entered the arena!\n\nNationality: {}; Style: {}\nHP: {}; AC: {}\nStr: {} ({}); Agi: {} ({}); Sta: {} ({}); Per: {} ({}); Int: {} ({}); Luc: {} ({})\nNotes: {}\nWeilding: {}",
-        glad.name, glad.nationality,
-        glad.style, glad.hp, glad.ac,
-        glad.strength, strength_mod,
-        glad.agility, agility_mod,
-        glad.stamina, stamina_mod,
-        glad.personality, personality_mod,
-        glad.inteligence, inteligence_mod,
-        glad.luck, luck_mod,
-        glad.notes,
-        glad.weapon.name
+#[command]
+async fn sheet(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    show_sheet(&Invocation::from_message(ctx, msg), args.rest()).await
+}
+
+async fn show_sheet(inv: &Invocation<'_>, name: &str) -> CommandResult {
+    match find_character(
+        name,
+        inv.guild_id.map(|guild_id| guild_id.0),
+        inv.author.id.0,
+    )? {
+        Some(glad) => {
+            inv.reply_embed(character_sheet(&glad)?).await?;
+        }
+        None => inv.reply("No gladiator goes by that name.").await?,
+    }
+
+    Ok(())
+}
+
+#[command]
+async fn profile(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    // The profile comes last, after the gladiator's name
+    let mut words: Vec<&str> = args.raw().collect();
+    let profile = words.pop().unwrap_or("");
+    set_profile(
+        &Invocation::from_message(ctx, msg),
+        &words.join(" "),
+        profile,
+    )
+    .await
+}
+
+async fn set_profile(inv: &Invocation<'_>, name: &str, profile: &str) -> CommandResult {
+    let profile = match Profile::parse(profile) {
+        Some(profile) => profile,
+        None => {
+            let names: Vec<&str> = Profile::ALL
+                .iter()
+                .map(|profile| profile.as_str())
+                .collect();
+            let out = format!("Fight how? Choose one of: {}", names.join(", "));
+            inv.reply(&out).await?;
+            return Ok(());
+        }
+    };
+
+    let glad = match find_character(
+        name,
+        inv.guild_id.map(|guild_id| guild_id.0),
+        inv.author.id.0,
+    )? {
+        Some(glad) if !is_owner(&glad, inv.author.id.0) => {
+            let out = format!("{} does not fight for you!", glad.name);
+            inv.reply(&out).await?;
+            return Ok(());
+        }
+        Some(glad) => glad,
+        None => {
+            inv.reply("No gladiator goes by that name.").await?;
+            return Ok(());
+        }
     };
-    msg.reply(ctx, &out).await?;
-    println!("{}", &out);
+
+    let db = open_db()?;
+    db.execute(
+        "UPDATE glads SET profile = ?1 WHERE id = ?2",
+        params![profile.as_str(), glad.id],
+    )?;
+    let out = format!("{} will fight {} from now on.", glad.name, profile.as_str());
+    inv.reply(&out).await?;
 
     Ok(())
 }
 
+// Armour class of the style, from the naked and desperate to the mounted
+fn style_colour(style: &str) -> Colour {
+    match style {
+        "Andabatae" | "Fugitivus" | "Pugilatus" => Colour::LIGHT_GREY,
+        "Velites" | "Retiarius" | "Laquearius" | "Sagittarius" => Colour::BLUE,
+        "Bestiarius" | "Thracian" | "Hoplomachus" | "Dimachaerus" => Colour::DARK_GREEN,
+        "Murmillo" | "Provacator" | "Scissor" | "Samnite" | "Cataphractarius" => Colour::RED,
+        "Eques" | "Essedarius" => Colour::GOLD,
+        _ => Colour::PURPLE,
+    }
+}
+
+fn character_sheet(glad: &Character) -> Result<CreateEmbed> {
+    let (wins, losses) = rating::get_record(glad.id)?;
+    let stats = [
+        ("Str", glad.strength),
+        ("Agi", glad.agility),
+        ("Sta", glad.stamina),
+        ("Per", glad.personality),
+        ("Int", glad.inteligence),
+        ("Luc", glad.luck),
+    ];
+
+    let hp = match glad.wounds {
+        0 => glad.hp.to_string(),
+        wounds => format!("{} of {}", (glad.hp - wounds).max(0), glad.hp),
+    };
+
+    let mut sheet = CreateEmbed::default();
+    sheet
+        .title(&glad.name)
+        .colour(style_colour(&glad.style))
+        .field("Nationality", &glad.nationality, true)
+        .field("Style", &glad.style, true)
+        .field("HP / AC", format!("{} / {}", hp, glad.ac), true)
+        .field("Fights", glad.profile.as_str(), true)
+        .field(
+            "Saves",
+            format!(
+                "Fort {:+} / Ref {:+} / Will {:+}",
+                glad.save_bonus(Save::Fortitude),
+                glad.save_bonus(Save::Reflex),
+                glad.save_bonus(Save::Willpower)
+            ),
+            false,
+        );
+    for (stat, score) in stats {
+        let mut value = format!("{} ({:+})", score, calc_modifier(score));
+        if stat == "Luc" && glad.luck_burned > 0 {
+            value.push_str(&format!(", {} burned", glad.luck_burned));
+        }
+        sheet.field(stat, value, true);
+    }
+    sheet
+        .field(
+            "Equipment",
+            format!(
+                "{} (d{}){}\n{}",
+                glad.weapon.name,
+                glad.weapon.damage_die,
+                match glad.proficiencies.contains(&glad.weapon.name) {
+                    true => ", proficient",
+                    false => "",
+                },
+                glad.notes
+            ),
+            false,
+        )
+        .field(
+            "Record",
+            format!(
+                "{} wins, {} losses, {} kills; level {}, rated {:.0}, fame {}",
+                wins, losses, glad.kills, glad.level, glad.rating, glad.fame
+            ),
+            false,
+        );
+    if !glad.titles.is_empty() {
+        sheet.field("Titles", &glad.titles, false);
+    }
+    if !glad.injuries.is_empty() {
+        let injuries: Vec<_> = glad.injuries.iter().map(|injury| injury.as_str()).collect();
+        sheet.field("Injuries", injuries.join(", "), false);
+    }
+    if glad.dead {
+        sheet.footer(|footer| footer.text("Fell in the arena"));
+    } else if glad.retired && glad.rudis {
+        sheet.footer(|footer| footer.text("Retired a free gladiator"));
+    } else if glad.retired {
+        sheet.footer(|footer| footer.text("Retired from the arena"));
+    } else if !matchmaking::can_fight(glad) {
+        sheet.footer(|footer| footer.text("Recovering from wounds"));
+    }
+
+    Ok(sheet)
+}
+
 #[command]
 async fn taunt(ctx: &Context, msg: &Message) -> CommandResult {
-    println!("{} asked me to taunt them!", msg.author.name);
+    taunt_player(&Invocation::from_message(ctx, msg)).await
+}
+
+async fn taunt_player(inv: &Invocation<'_>) -> CommandResult {
+    println!("{} asked me to taunt them!", inv.author.name);
     let quote = get_quote().to_uppercase();
-    msg.reply(ctx, &quote).await?;
+    inv.reply(&quote).await?;
 
     Ok(())
 }
 
 #[command]
-async fn fight(ctx: &Context, msg: &Message) -> CommandResult {
+async fn fight(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    start_fight(&Invocation::from_message(ctx, msg), args.rest(), false).await
+}
+
+async fn start_fight(inv: &Invocation<'_>, name: &str, interactive: bool) -> CommandResult {
     let command = format!(
         "{} commands that two gladiators fight to the death!",
-        msg.author.name
+        inv.author.name
     );
-    msg.reply(ctx.clone(), &command).await?;
+    inv.reply(&command).await?;
 
     let quote = get_quote().to_uppercase();
-    msg.reply(ctx.clone(), &quote).await?;
-    let characters = get_characters(2).unwrap();
+    inv.reply(&quote).await?;
+
+    let glad1 = match name {
+        "" => matchmaking::find_fighter(inv.author.id.0, inv.guild_id.map(|guild_id| guild_id.0))?,
+        name => find_character(
+            name,
+            inv.guild_id.map(|guild_id| guild_id.0),
+            inv.author.id.0,
+        )?,
+    };
+    let glad1 = match glad1 {
+        Some(glad) if !in_guild(&glad, inv.guild_id.map(|guild_id| guild_id.0)) => {
+            let out = format!("{} fights in another arena.", glad.name);
+            inv.reply(&out).await?;
+            return Ok(());
+        }
+        Some(glad) if !is_owner(&glad, inv.author.id.0) => {
+            let out = format!(
+                "{} is not yours to send to their death! Try `/challenge` instead.",
+                glad.name
+            );
+            inv.reply(&out).await?;
+            return Ok(());
+        }
+        Some(glad) if matchmaking::can_fight(&glad) => glad,
+        Some(glad) => {
+            let out = format!("{} is in no state to fight!", glad.name);
+            inv.reply(&out).await?;
+            return Ok(());
+        }
+        None => {
+            inv.reply("There are not enough gladiators to fight!")
+                .await?;
+            return Ok(());
+        }
+    };
+    let glad2 = match matchmaking::find_opponent(&glad1, inv.author.id.0)? {
+        Some(glad) => glad,
+        None => {
+            let out = format!(
+                "There is nobody fit to face {}! Other players' gladiators have to be fought with `/challenge`.",
+                glad1.name
+            );
+            inv.reply(&out).await?;
+            return Ok(());
+        }
+    };
 
-    // Roll for initiative
-    let mut gladiators = Vec::new();
-    for mut glad in characters.clone() {
-        glad.initiative = roller(1, 20) + calc_modifier(glad.agility);
-        gladiators.push(glad)
+    let mut bout = Bout::new(glad1, glad2);
+    bout.lethal = true;
+    narrate(inv, &mut bout, interactive).await;
+    combat::record(&bout)?;
+    if let Some(obituary) = hall::obituary(&bout)? {
+        inv.reply_embed(obituary).await?;
     }
-    gladiators.sort_by_key(|d| d.initiative);
-    gladiators.reverse();
 
-    let mut glad1 = gladiators.pop().unwrap();
-    let mut glad2 = gladiators.pop().unwrap();
+    Ok(())
+}
 
-    loop {
-        println!("{}'s Current HP: {}", glad1.name, glad1.hp);
-        println!("{}'s Current HP: {}", glad2.name, glad2.hp);
-
-        // Gladiator 1
-        let mut attack_modifier = calc_modifier(glad1.strength);
-        let mut dmg_modifier = calc_modifier(glad1.strength);
-        if !glad1.weapon.is_melee {
-            attack_modifier = calc_modifier(glad1.agility);
-            dmg_modifier = 0;
+// Plays a bout out to the end, narrating it while Discord cooperates. If a
+// message can't be sent or edited the rest is fought out unseen, so the bout
+// can always be recorded and any stakes settled
+async fn narrate(inv: &Invocation<'_>, bout: &mut Bout, interactive: bool) {
+    if let Err(e) = narrate_live(inv, bout, interactive).await {
+        println!("error narrating fight: {}", e);
+    }
+    while !bout.is_over() {
+        bout.play_round();
+    }
+}
+
+// Plays a bout out in a single message, edited round by round. Interactive
+// bouts wait on each owner to pick their gladiator's action with the buttons
+async fn narrate_live(inv: &Invocation<'_>, bout: &mut Bout, interactive: bool) -> CommandResult {
+    let mut message = inv.reply_embed(fight_embed(bout, &[])).await?;
+    let pace = fight_pace();
+
+    // With threads on, the opening message stays in the channel to carry the
+    // result and the play-by-play goes in a thread hung off it
+    let mut result = None;
+    if env::var("GLADBOT_FIGHT_THREADS").is_ok() && inv.guild_id.is_some() {
+        match open_fight_thread(inv, &message, bout).await {
+            Ok(live) => result = Some(std::mem::replace(&mut message, live)),
+            Err(e) => println!("error opening fight thread: {}", e),
         }
-        let to_hit = roller(1, 20) + attack_modifier;
-        if to_hit >= glad2.ac {
-            let dmg = roller(1, glad1.weapon.damage_die) + dmg_modifier;
-            let status = get_hit_msg(
-                glad1.weapon.name.clone(),
-                glad1.name.clone(),
-                glad2.name.clone(),
-                dmg,
-            );
-            msg.reply(ctx.clone(), &status).await?;
-            glad2.hp -= dmg;
-            if glad2.hp <= 0 {
-                let status = format!("{} has been defeated in mortal combat!", glad2.name);
-                msg.reply(ctx.clone(), &status).await?;
-                break;
+    }
+
+    if interactive {
+        message
+            .edit(inv.ctx, |edit| edit.components(action_buttons))
+            .await?;
+    }
+
+    while !bout.is_over() {
+        for glad in bout.gladiators.iter() {
+            println!("{}'s Current HP: {}", glad.name, glad.hp);
+        }
+
+        let log = match interactive {
+            true => {
+                let actions = choose_actions(inv, &message, bout).await?;
+                bout.play_round_with(actions)
+            }
+            false => {
+                tokio::time::sleep(pace).await;
+                bout.play_round()
+            }
+        };
+        message
+            .edit(inv.ctx, |edit| {
+                edit.set_embed(fight_embed(bout, &log));
+                match interactive && !bout.is_over() {
+                    true => edit.components(action_buttons),
+                    false => edit.components(|components| components),
+                }
+            })
+            .await?;
+    }
+
+    if let Some(mut result) = result {
+        result
+            .edit(inv.ctx, |edit| edit.set_embed(fight_embed(bout, &[])))
+            .await?;
+    }
+
+    Ok(())
+}
+
+fn action_buttons(components: &mut CreateComponents) -> &mut CreateComponents {
+    components.create_action_row(|row| {
+        row.create_button(|button| {
+            button
+                .custom_id(BURN_LUCK)
+                .label("Burn Luck")
+                .style(ButtonStyle::Secondary)
+        })
+    });
+    for actions in Action::ALL.chunks(BUTTONS_PER_ROW) {
+        components.create_action_row(|row| {
+            for action in actions {
+                row.create_button(|button| {
+                    let style = match action {
+                        Action::Yield => ButtonStyle::Danger,
+                        Action::MightyDeed => ButtonStyle::Success,
+                        _ => ButtonStyle::Primary,
+                    };
+                    button
+                        .custom_id(action.as_str())
+                        .label(action.label())
+                        .style(style)
+                });
             }
-        } else {
-            let status = format!("{} misses their attack", glad1.name);
-            msg.reply(ctx.clone(), &status).await?;
+            row
+        });
+    }
+    components
+}
+
+// Collects each owner's orders for the round, anyone who doesn't answer in
+// time, or has no owner to answer for them, fights on instinct
+async fn choose_actions(
+    inv: &Invocation<'_>,
+    message: &Message,
+    bout: &mut Bout,
+) -> serenity::Result<[Action; 2]> {
+    let owners = [bout.gladiators[0].owner_id, bout.gladiators[1].owner_id];
+    let mut actions = [None, None];
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(TURN_SECONDS);
+
+    while (0..2).any(|side| actions[side].is_none() && owners[side].is_some()) {
+        let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+        let press = match message
+            .await_component_interaction(inv.ctx)
+            .timeout(remaining)
+            .await
+        {
+            Some(press) => press,
+            None => break,
+        };
+
+        let side =
+            (0..2).find(|&side| actions[side].is_none() && owners[side] == Some(press.user.id.0));
+        let out = match (side, press.data.custom_id.as_str()) {
+            // Burning luck goes along with the action rather than replacing it
+            (Some(side), BURN_LUCK) => {
+                bout.luck_orders[side] = Some(true);
+                format!(
+                    "{} will burn luck if it comes to it.",
+                    bout.gladiators[side].name
+                )
+            }
+            (Some(side), custom_id) => match Action::parse(custom_id) {
+                Some(action) => {
+                    actions[side] = Some(action);
+                    bout.luck_orders[side].get_or_insert(false);
+                    format!("{} will {}.", bout.gladiators[side].name, action.label())
+                }
+                None => "That's no order a gladiator knows.".to_string(),
+            },
+            _ => "You have no gladiator waiting on orders here.".to_string(),
+        };
+        press
+            .create_interaction_response(&inv.ctx.http, |response| {
+                response
+                    .kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|message| message.content(out).ephemeral(true))
+            })
+            .await?;
+    }
+
+    Ok([0, 1].map(|side| actions[side].unwrap_or_else(|| bout.ai_action(side))))
+}
+
+// Starts a thread named after the combatants, returning the message in it
+// that will follow the fight
+async fn open_fight_thread(
+    inv: &Invocation<'_>,
+    opening: &Message,
+    bout: &Bout,
+) -> serenity::Result<Message> {
+    let [glad1, glad2] = &bout.gladiators;
+    let name: String = format!("{} vs {}", glad1.name, glad2.name)
+        .chars()
+        .take(THREAD_NAME_LENGTH)
+        .collect();
+
+    let thread = inv
+        .channel_id
+        .create_public_thread(&inv.ctx.http, opening.id, |thread| {
+            thread
+                .name(name)
+                .auto_archive_duration(THREAD_ARCHIVE_MINUTES)
+        })
+        .await?;
+    thread
+        .id
+        .send_message(&inv.ctx.http, |message| {
+            message.set_embed(fight_embed(bout, &[]))
+        })
+        .await
+}
+
+// Seconds between rounds, from GLADBOT_FIGHT_PACE if it's set
+fn fight_pace() -> Duration {
+    let seconds = env::var("GLADBOT_FIGHT_PACE")
+        .ok()
+        .and_then(|pace| pace.parse().ok())
+        .unwrap_or(DEFAULT_FIGHT_PACE);
+    Duration::from_secs(seconds)
+}
+
+fn hp_bar(hp: i8, max_hp: i8) -> String {
+    let hp = hp.max(0) as usize;
+    let max_hp = max_hp.max(1) as usize;
+    let filled = (HP_BAR_WIDTH * hp).div_ceil(max_hp).min(HP_BAR_WIDTH);
+    format!(
+        "{}{} {}/{}",
+        "█".repeat(filled),
+        "░".repeat(HP_BAR_WIDTH - filled),
+        hp,
+        max_hp
+    )
+}
+
+fn fight_embed(bout: &Bout, log: &[String]) -> CreateEmbed {
+    let [glad1, glad2] = &bout.gladiators;
+    let mut embed = CreateEmbed::default();
+    embed.title(format!("{} faces {}", glad1.name, glad2.name));
+    for (side, glad) in bout.gladiators.iter().enumerate() {
+        let mut status = hp_bar(glad.hp, bout.max_hp[side]);
+        let mut conditions = bout.condition_names(side);
+        if bout.fatigue_penalty(side) > 0 {
+            conditions.push("tiring");
         }
+        if !conditions.is_empty() {
+            status.push_str(&format!("\n*{}*", conditions.join(", ")));
+        }
+        embed.field(&glad.name, status, true);
+    }
 
-        // Gladiator 2
-        let mut attack_modifier = calc_modifier(glad2.strength);
-        let mut dmg_modifier = calc_modifier(glad2.strength);
-        if !glad2.weapon.is_melee {
-            attack_modifier = calc_modifier(glad2.agility);
-            dmg_modifier = 0;
+    match (bout.winner(), bout.loser()) {
+        (Some(winner), Some(loser)) => {
+            let fate = match (bout.yielded, bout.decision, bout.lethal) {
+                (Some(_), _, _) => "at the crowd's mercy",
+                (None, Some(_), _) => "beaten on the judges' decision",
+                (None, None, true) => "slain",
+                (None, None, false) => "beaten",
+            };
+            embed.colour(Colour::GOLD).description(format!(
+                "**{}** stands victorious after {} rounds with {} HP left, {} lies {}.",
+                winner.name, bout.round, winner.hp, loser.name, fate
+            ));
         }
-        let to_hit = roller(1, 20) + attack_modifier;
-        if to_hit >= glad1.ac {
-            let dmg = roller(1, glad2.weapon.damage_die) + dmg_modifier;
-            let status = get_hit_msg(
-                glad2.weapon.name.clone(),
-                glad2.name.clone(),
-                glad1.name.clone(),
-                dmg,
-            );
-            msg.reply(ctx.clone(), &status).await?;
-            glad1.hp -= dmg;
-            if glad1.hp <= 0 {
-                let status = format!("{} has been defeated in mortal combat!", glad1.name);
-                msg.reply(ctx.clone(), &status).await?;
-                break;
-            }
-        } else {
-            let status = format!("{} misses their attack", glad2.name);
-            msg.reply(ctx.clone(), &status).await?;
+        _ => {
+            embed.colour(Colour::DARK_RED);
         }
     }
+    if !log.is_empty() {
+        embed.field(format!("Round {}", bout.round), log.join("\n"), false);
+    }
 
-    Ok(())
+    embed
 }
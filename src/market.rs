@@ -0,0 +1,533 @@
+use std::env;
+
+use serenity::builder::CreateApplicationCommands;
+use serenity::client::Context;
+use serenity::framework::standard::{
+    macros::{command, group},
+    Args, CommandResult,
+};
+use serenity::model::application::command::CommandOptionType;
+use serenity::model::application::interaction::application_command::CommandDataOption;
+use serenity::model::channel::Message;
+use serenity::model::id::ChannelId;
+
+use rusqlite::{params, Connection, Result, Row};
+
+use crate::slash::{option_int, subcommand, Invocation};
+use crate::{
+    calc_modifier, character_sheet, gen_character, get_character, ludus, now, open_db, purse,
+    rating, Character,
+};
+
+// How many recruits go on sale each day, and for how long
+const MARKET_SIZE: usize = 5;
+const MARKET_HOURS: i64 = 24;
+// Prices start here and move by each stat modifier and point of HP
+const BASE_PRICE: i64 = 40;
+const PRICE_PER_MODIFIER: i64 = 10;
+const PRICE_PER_HP: i64 = 3;
+const MIN_PRICE: i64 = 10;
+// Bidding opens at this share of the asking price
+const MIN_BID_PERCENT: i64 = 50;
+
+#[group]
+#[commands(market)]
+struct Markets;
+
+#[derive(Clone, Debug)]
+struct Recruit {
+    id: i64,
+    guild_id: u64,
+    channel_id: u64,
+    name: String,
+    style: String,
+    summary: String,
+    price: i64,
+    bid: i64,
+    bidder_id: Option<u64>,
+    closes_at: i64,
+}
+
+fn open() -> Result<Connection> {
+    let db = open_db()?;
+
+    match db.execute_batch(
+        "
+    CREATE TABLE IF NOT EXISTS recruits (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        guild_id INTEGER,
+        channel_id INTEGER,
+        name STRING,
+        nationality STRING,
+        style STRING,
+        hp INTEGER,
+        ac INTEGER,
+        strength INTEGER,
+        agility INTEGER,
+        stamina INTEGER,
+        personality INTEGER,
+        inteligence INTEGER,
+        luck INTEGER,
+        notes STRING,
+        initiative INTEGER,
+        weapon_key STRING,
+        price INTEGER,
+        bid INTEGER NOT NULL DEFAULT 0,
+        bidder_id INTEGER,
+        closes_at INTEGER,
+        status STRING NOT NULL DEFAULT 'open'
+    );",
+    ) {
+        Ok(result) => result,
+        Err(e) => {
+            println!("error creating recruit table: {}", e);
+            return Err(e);
+        }
+    };
+
+    Ok(db)
+}
+
+// With the market on, new gladiators have to be bought rather than summoned
+pub fn is_open() -> bool {
+    env::var("GLADBOT_MARKET").is_ok()
+}
+
+fn price(glad: &Character) -> i64 {
+    let modifiers: i64 = [
+        glad.strength,
+        glad.agility,
+        glad.stamina,
+        glad.personality,
+        glad.inteligence,
+        glad.luck,
+    ]
+    .iter()
+    .map(|stat| calc_modifier(*stat) as i64)
+    .sum();
+    (BASE_PRICE + PRICE_PER_MODIFIER * modifiers + PRICE_PER_HP * glad.hp as i64).max(MIN_PRICE)
+}
+
+fn row_to_recruit(row: &Row) -> Result<Recruit> {
+    Ok(Recruit {
+        id: row.get(0)?,
+        guild_id: row.get(1)?,
+        channel_id: row.get(2)?,
+        name: row.get(3)?,
+        style: row.get(4)?,
+        summary: row.get(5)?,
+        price: row.get(6)?,
+        bid: row.get(7)?,
+        bidder_id: row.get(8)?,
+        closes_at: row.get(9)?,
+    })
+}
+
+const RECRUIT_COLUMNS: &str = "id, guild_id, channel_id, name, style,
+    'Str ' || strength || ', Agi ' || agility || ', Sta ' || stamina || ', Per ' || personality
+    || ', Int ' || inteligence || ', Luc ' || luck || ', HP ' || hp || ', AC ' || ac,
+    price, bid, bidder_id, closes_at";
+
+fn get_market(guild_id: u64) -> Result<Vec<Recruit>> {
+    let db = open()?;
+
+    let mut stmt = db.prepare(&format!(
+        "SELECT {} FROM recruits WHERE guild_id = ?1 AND status = 'open' ORDER BY id",
+        RECRUIT_COLUMNS
+    ))?;
+    let rows = stmt.query_map([guild_id], row_to_recruit)?;
+    rows.collect()
+}
+
+fn get_closed() -> Result<Vec<Recruit>> {
+    let db = open()?;
+
+    let mut stmt = db.prepare(&format!(
+        "SELECT {} FROM recruits WHERE status = 'open' AND closes_at <= ?1",
+        RECRUIT_COLUMNS
+    ))?;
+    let rows = stmt.query_map([now()], row_to_recruit)?;
+    rows.collect()
+}
+
+// Rolls a fresh batch of recruits, the first time anyone looks each day
+fn stock_market(guild_id: u64, channel_id: u64) -> Result<()> {
+    let db = open()?;
+
+    for _ in 0..MARKET_SIZE {
+        let glad = gen_character();
+        db.execute(
+            "INSERT INTO recruits (guild_id, channel_id, name, nationality, style, hp, ac, strength, agility, stamina, personality, inteligence, luck, notes, initiative, weapon_key, price, closes_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)",
+            params![
+                guild_id,
+                channel_id,
+                glad.name,
+                glad.nationality,
+                glad.style,
+                glad.hp,
+                glad.ac,
+                glad.strength,
+                glad.agility,
+                glad.stamina,
+                glad.personality,
+                glad.inteligence,
+                glad.luck,
+                glad.notes,
+                glad.initiative,
+                glad.weapon.name,
+                price(&glad),
+                now() + MARKET_HOURS * 3600
+            ],
+        )?;
+    }
+
+    Ok(())
+}
+
+// Moves a recruit into the buyer's ludus, returning the new gladiator, or
+// None if they were already sold
+fn sell(recruit: &Recruit, owner_id: u64) -> Result<Option<i64>> {
+    let db = open()?;
+
+    if !close(&db, recruit, "sold")? {
+        return Ok(None);
+    }
+    db.execute(
+        "INSERT INTO glads (name, nationality, style, hp, ac, strength, agility, stamina, personality, inteligence, luck, notes, initiative, weapon_key, owner_id, guild_id, rating, level)
+        SELECT name, nationality, style, hp, ac, strength, agility, stamina, personality, inteligence, luck, notes, initiative, weapon_key, ?1, guild_id, ?2, 1
+        FROM recruits WHERE id = ?3",
+        params![owner_id, rating::START_RATING, recruit.id],
+    )?;
+
+    Ok(Some(db.last_insert_rowid()))
+}
+
+// Takes a recruit off the market, returning false if they already were
+fn close(db: &Connection, recruit: &Recruit, status: &str) -> Result<bool> {
+    let updated = db.execute(
+        "UPDATE recruits SET status = ?1 WHERE id = ?2 AND status = 'open'",
+        params![status, recruit.id],
+    )?;
+
+    Ok(updated == 1)
+}
+
+// Only replaces the bid the player saw, returning false if the recruit was
+// sold or outbid in the meantime
+fn place_bid(recruit: &Recruit, bidder_id: u64, denarii: i64) -> Result<bool> {
+    let db = open()?;
+
+    let updated = db.execute(
+        "UPDATE recruits SET bid = ?1, bidder_id = ?2
+        WHERE id = ?3 AND status = 'open' AND bid = ?4 AND bidder_id IS ?5",
+        params![
+            denarii,
+            bidder_id,
+            recruit.id,
+            recruit.bid,
+            recruit.bidder_id
+        ],
+    )?;
+
+    Ok(updated == 1)
+}
+
+// Hands the standing bid back to whoever made it
+fn refund(recruit: &Recruit) -> Result<()> {
+    if let Some(bidder_id) = recruit.bidder_id {
+        purse::credit(recruit.guild_id, bidder_id, recruit.bid)?;
+    }
+
+    Ok(())
+}
+
+// Called by the scheduler to settle the day's bidding. The highest bidder
+// gets their recruit if they have room for them, the rest go unsold
+pub async fn tick(ctx: &Context) {
+    let closed = match get_closed() {
+        Ok(closed) => closed,
+        Err(e) => {
+            println!("error loading recruits: {}", e);
+            return;
+        }
+    };
+
+    for recruit in closed {
+        let out = match settle(&recruit) {
+            Ok(Some(out)) => out,
+            Ok(None) => continue,
+            Err(e) => {
+                println!("error settling recruit {}: {}", recruit.id, e);
+                continue;
+            }
+        };
+        if let Err(e) = ChannelId(recruit.channel_id).say(&ctx.http, &out).await {
+            println!("error posting market result: {}", e);
+        }
+    }
+}
+
+fn settle(recruit: &Recruit) -> Result<Option<String>> {
+    let db = open()?;
+    let bidder_id = match recruit.bidder_id {
+        Some(bidder_id) => bidder_id,
+        None => {
+            close(&db, recruit, "unsold")?;
+            return Ok(None);
+        }
+    };
+    if !ludus::has_room(recruit.guild_id, bidder_id)? {
+        if !close(&db, recruit, "unsold")? {
+            return Ok(None);
+        }
+        refund(recruit)?;
+        let out = format!(
+            "<@{}> won the bidding for {} but has no room in their ludus. The bid is returned.",
+            bidder_id, recruit.name
+        );
+        return Ok(Some(out));
+    }
+
+    if sell(recruit, bidder_id)?.is_none() {
+        return Ok(None);
+    }
+    Ok(Some(format!(
+        "<@{}> wins {} the {} at market for {} denarii!",
+        bidder_id, recruit.name, recruit.style, recruit.bid
+    )))
+}
+
+pub fn register(commands: &mut CreateApplicationCommands) -> &mut CreateApplicationCommands {
+    commands.create_application_command(|command| {
+        command
+            .name("market")
+            .description("Today's recruits for sale")
+            .create_option(|option| {
+                option
+                    .name("show")
+                    .description("See who's for sale")
+                    .kind(CommandOptionType::SubCommand)
+            })
+            .create_option(|option| {
+                option
+                    .name("buy")
+                    .description("Buy a recruit outright at the asking price")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|option| {
+                        option
+                            .name("number")
+                            .description("Their number at market")
+                            .kind(CommandOptionType::Integer)
+                            .min_int_value(1)
+                            .required(true)
+                    })
+            })
+            .create_option(|option| {
+                option
+                    .name("bid")
+                    .description("Bid on a recruit, settled when the market closes")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|option| {
+                        option
+                            .name("number")
+                            .description("Their number at market")
+                            .kind(CommandOptionType::Integer)
+                            .min_int_value(1)
+                            .required(true)
+                    })
+                    .create_sub_option(|option| {
+                        option
+                            .name("denarii")
+                            .description("Your bid")
+                            .kind(CommandOptionType::Integer)
+                            .min_int_value(1)
+                            .required(true)
+                    })
+            })
+    })
+}
+
+pub async fn run(inv: &Invocation<'_>, options: &[CommandDataOption]) -> CommandResult {
+    match subcommand(options) {
+        Some(("buy", options)) => {
+            buy_recruit(inv, option_int(options, "number").unwrap_or(0)).await
+        }
+        Some(("bid", options)) => {
+            let number = option_int(options, "number").unwrap_or(0);
+            bid_on_recruit(inv, number, option_int(options, "denarii").unwrap_or(0)).await
+        }
+        _ => show_market(inv).await,
+    }
+}
+
+#[command]
+#[sub_commands(buy, bid)]
+async fn market(ctx: &Context, msg: &Message) -> CommandResult {
+    show_market(&Invocation::from_message(ctx, msg)).await
+}
+
+// The market only runs in servers, and only when turned on
+async fn market_guild(inv: &Invocation<'_>) -> serenity::Result<Option<u64>> {
+    let out = match (is_open(), inv.guild_id) {
+        (true, Some(guild_id)) => return Ok(Some(guild_id.0)),
+        (false, _) => "There's no market here, `/glad` recruits a gladiator for free.",
+        (true, None) => "The market is only held in servers.",
+    };
+    inv.reply(out).await?;
+    Ok(None)
+}
+
+async fn show_market(inv: &Invocation<'_>) -> CommandResult {
+    let guild_id = match market_guild(inv).await? {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+
+    let mut recruits = get_market(guild_id)?;
+    if recruits.is_empty() {
+        stock_market(guild_id, inv.channel_id.0)?;
+        recruits = get_market(guild_id)?;
+    }
+
+    let hours = (recruits[0].closes_at - now() + 3599) / 3600;
+    let mut out = format!("**Recruits for sale**, bidding closes in {} hours\n", hours);
+    for recruit in recruits.iter() {
+        let bid = match recruit.bidder_id {
+            Some(bidder_id) => format!(", bid {} by <@{}>", recruit.bid, bidder_id),
+            None => String::new(),
+        };
+        out.push_str(&format!(
+            "{}. **{}** the {} ({}), {} denarii{}\n",
+            recruit.id, recruit.name, recruit.style, recruit.summary, recruit.price, bid
+        ));
+    }
+    out.push_str(
+        "Buy one with `/market buy <number>` or bid with `/market bid <number> <denarii>`",
+    );
+    inv.reply(&out).await?;
+
+    Ok(())
+}
+
+async fn find_recruit(
+    inv: &Invocation<'_>,
+    guild_id: u64,
+    number: i64,
+) -> CommandResult<Option<Recruit>> {
+    // Numbered by id, so a sale doesn't shift everyone else's number
+    let recruit = get_market(guild_id)?
+        .into_iter()
+        .find(|recruit| recruit.id == number);
+    if recruit.is_none() {
+        inv.reply("There's no recruit by that number, see `/market`.")
+            .await?;
+    }
+
+    Ok(recruit)
+}
+
+#[command]
+async fn buy(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let number = args.single::<i64>().unwrap_or(0);
+    buy_recruit(&Invocation::from_message(ctx, msg), number).await
+}
+
+async fn buy_recruit(inv: &Invocation<'_>, number: i64) -> CommandResult {
+    let guild_id = match market_guild(inv).await? {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+    let recruit = match find_recruit(inv, guild_id, number).await? {
+        Some(recruit) => recruit,
+        None => return Ok(()),
+    };
+
+    let owner_id = inv.author.id.0;
+    if !ludus::has_room(guild_id, owner_id)? {
+        inv.reply("Your ludus is full! Build more barracks with `/ludus buy barracks`.")
+            .await?;
+        return Ok(());
+    }
+    if !purse::debit(guild_id, owner_id, recruit.price)? {
+        let out = format!(
+            "{} costs {} denarii, which you don't have.",
+            recruit.name, recruit.price
+        );
+        inv.reply(&out).await?;
+        return Ok(());
+    }
+
+    let glad_id = match sell(&recruit, owner_id)? {
+        Some(glad_id) => glad_id,
+        None => {
+            purse::credit(guild_id, owner_id, recruit.price)?;
+            let out = format!("Too late, {} has already been sold.", recruit.name);
+            inv.reply(&out).await?;
+            return Ok(());
+        }
+    };
+    refund(&recruit)?;
+    let glad = get_character(glad_id)?;
+    rating::register_owner(guild_id, owner_id, &inv.author.name)?;
+
+    let mut sheet = character_sheet(&glad)?;
+    sheet.description(format!(
+        "{} joins your ludus for {} denarii!",
+        glad.name, recruit.price
+    ));
+    inv.reply_embed(sheet).await?;
+
+    Ok(())
+}
+
+#[command]
+async fn bid(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let number = args.single::<i64>().unwrap_or(0);
+    let denarii = args.single::<i64>().unwrap_or(0);
+    bid_on_recruit(&Invocation::from_message(ctx, msg), number, denarii).await
+}
+
+// Bids are taken from the purse when made and handed back when beaten
+async fn bid_on_recruit(inv: &Invocation<'_>, number: i64, denarii: i64) -> CommandResult {
+    let guild_id = match market_guild(inv).await? {
+        Some(guild_id) => guild_id,
+        None => return Ok(()),
+    };
+    let recruit = match find_recruit(inv, guild_id, number).await? {
+        Some(recruit) => recruit,
+        None => return Ok(()),
+    };
+
+    let opening = recruit.price * MIN_BID_PERCENT / 100;
+    let out = if denarii >= recruit.price {
+        format!(
+            "At that price just buy {} with `/market buy {}`.",
+            recruit.name, number
+        )
+    } else if denarii < opening || denarii <= recruit.bid {
+        format!(
+            "You'll have to bid more than that, at least {} denarii.",
+            opening.max(recruit.bid + 1)
+        )
+    } else if !purse::debit(guild_id, inv.author.id.0, denarii)? {
+        format!("You can't cover a bid of {} denarii.", denarii)
+    } else if !place_bid(&recruit, inv.author.id.0, denarii)? {
+        purse::credit(guild_id, inv.author.id.0, denarii)?;
+        format!(
+            "{} has been sold or bid on since, see `/market`.",
+            recruit.name
+        )
+    } else {
+        refund(&recruit)?;
+        format!(
+            "You bid {} denarii for {}. The market closes in {} hours.",
+            denarii,
+            recruit.name,
+            (recruit.closes_at - now() + 3599) / 3600
+        )
+    };
+    inv.reply(&out).await?;
+
+    Ok(())
+}
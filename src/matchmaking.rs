@@ -0,0 +1,80 @@
+use rand::seq::SliceRandom;
+use rusqlite::{params, Result};
+
+use crate::{is_owner, now, open_db, row_to_character, Character, GLAD_COLUMNS};
+
+// How many rating points a level of difference is worth when pairing
+const LEVEL_WEIGHT: f64 = 100.0;
+// Pick at random among this many of the closest matches so the same
+// pairings don't come up every time
+const SHORTLIST: usize = 3;
+
+pub fn can_fight(glad: &Character) -> bool {
+    !glad.dead && !glad.retired && glad.wounded_until <= now()
+}
+
+// Everyone fit to fight in the server, plus gladiators from before owners
+// were tracked who belong to no server in particular and so can face anyone
+fn get_eligible(guild_id: Option<u64>, exclude_id: i64) -> Result<Vec<Character>> {
+    let db = open_db()?;
+
+    let mut stmt = db.prepare(&format!(
+        "SELECT {} FROM glads
+        WHERE dead = 0 AND retired = 0 AND wounded_until <= ?1 AND (?2 IS NULL OR guild_id IS NULL OR guild_id = ?2) AND id != ?3",
+        GLAD_COLUMNS
+    ))?;
+    let rows = stmt.query_map(params![now(), guild_id, exclude_id], row_to_character)?;
+    rows.collect()
+}
+
+fn distance(glad: &Character, opponent: &Character) -> f64 {
+    (glad.rating - opponent.rating).abs()
+        + LEVEL_WEIGHT * (glad.level as f64 - opponent.level as f64).abs()
+}
+
+// Picks a gladiator for someone who didn't name one, preferring their own
+// over the unowned ones anybody may send out
+pub fn find_fighter(owner_id: u64, guild_id: Option<u64>) -> Result<Option<Character>> {
+    let eligible = get_eligible(guild_id, 0)?;
+    let own: Vec<&Character> = eligible
+        .iter()
+        .filter(|glad| glad.owner_id == Some(owner_id))
+        .collect();
+    let unowned: Vec<&Character> = eligible
+        .iter()
+        .filter(|glad| glad.owner_id.is_none())
+        .collect();
+
+    let mut rng = rand::thread_rng();
+    let fighter = own
+        .choose(&mut rng)
+        .or_else(|| unowned.choose(&mut rng))
+        .map(|glad| (*glad).clone());
+
+    Ok(fighter)
+}
+
+// The closest match by rating and level who isn't who they just fought,
+// unless there's nobody else. Only the player's own gladiators or unowned
+// ones, anyone else's has to be challenged
+pub fn find_opponent(glad: &Character, owner_id: u64) -> Result<Option<Character>> {
+    let eligible: Vec<Character> = get_eligible(glad.guild_id, glad.id)?
+        .into_iter()
+        .filter(|opponent| is_owner(opponent, owner_id))
+        .collect();
+    let mut candidates: Vec<Character> = eligible
+        .iter()
+        .filter(|opponent| {
+            glad.last_opponent_id != Some(opponent.id) && opponent.last_opponent_id != Some(glad.id)
+        })
+        .cloned()
+        .collect();
+    if candidates.is_empty() {
+        candidates = eligible;
+    }
+
+    candidates.sort_by(|a, b| distance(glad, a).partial_cmp(&distance(glad, b)).unwrap());
+    candidates.truncate(SHORTLIST);
+
+    Ok(candidates.choose(&mut rand::thread_rng()).cloned())
+}
@@ -0,0 +1,208 @@
+use serenity::builder::CreateApplicationCommands;
+use serenity::client::Context;
+use serenity::framework::standard::{
+    macros::{command, group},
+    Args, CommandResult,
+};
+use serenity::model::application::interaction::application_command::CommandDataOption;
+use serenity::model::channel::Message;
+
+use rusqlite::{params, Result};
+
+use crate::combat::Injury;
+use crate::slash::{gladiator_option, option_str, Invocation};
+use crate::{
+    calc_modifier, find_character, is_owner, ludus, now, open_db, purse, roller, row_to_character,
+    Character, GLAD_COLUMNS,
+};
+
+// Wounds heal a day of rest at a time
+const REST_SECONDS: i64 = 24 * 3600;
+// What the medicus charges to stitch up wounds, and to set right an injury
+// that can be set right
+const TREATMENT_FEE: i64 = 20;
+const CURE_FEE: i64 = 75;
+
+#[group]
+#[commands(medicus)]
+struct Medici;
+
+// HP a gladiator gets back for a day of rest, and another in a ludus with
+// a valetudinarium
+fn rest_healing(glad: &Character) -> Result<i8> {
+    let infirmary = ludus::get_home(glad.guild_id, glad.owner_id)?
+        .map(|ludus| ludus.infirmary)
+        .unwrap_or(false);
+    Ok((glad.level + calc_modifier(glad.stamina)).max(1) + infirmary as i8)
+}
+
+fn get_resting() -> Result<Vec<Character>> {
+    let db = open_db()?;
+
+    let mut stmt = db.prepare(&format!(
+        "SELECT {} FROM glads WHERE dead = 0 AND wounds > 0 AND rested_since <= ?1",
+        GLAD_COLUMNS
+    ))?;
+    let rows = stmt.query_map([now() - REST_SECONDS], row_to_character)?;
+    rows.collect()
+}
+
+fn heal(glad: &Character, healing: i8) -> Result<()> {
+    let db = open_db()?;
+
+    db.execute(
+        "UPDATE glads SET wounds = MAX(0, wounds - ?1), rested_since = rested_since + ?2
+        WHERE id = ?3",
+        params![healing, REST_SECONDS, glad.id],
+    )?;
+
+    Ok(())
+}
+
+// Called by the scheduler to heal everyone who has rested a full day
+pub async fn tick(_ctx: &Context) {
+    let resting = match get_resting() {
+        Ok(resting) => resting,
+        Err(e) => {
+            println!("error loading wounded gladiators: {}", e);
+            return;
+        }
+    };
+
+    for glad in resting {
+        if let Err(e) = rest_healing(&glad).and_then(|healing| heal(&glad, healing)) {
+            println!("error healing {}: {}", glad.name, e);
+        }
+    }
+}
+
+pub fn register(commands: &mut CreateApplicationCommands) -> &mut CreateApplicationCommands {
+    commands.create_application_command(|command| {
+        command
+            .name("medicus")
+            .description("Pay the medicus to treat a gladiator's wounds or injuries")
+            .create_option(|option| gladiator_option(option, "Your gladiator", true))
+    })
+}
+
+pub async fn run(inv: &Invocation<'_>, options: &[CommandDataOption]) -> CommandResult {
+    treat(inv, option_str(options, "gladiator").unwrap_or("")).await
+}
+
+#[command]
+async fn medicus(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    treat(&Invocation::from_message(ctx, msg), args.rest()).await
+}
+
+// Fresh wounds come first; only a gladiator who is whole again can have an
+// old injury seen to
+async fn treat(inv: &Invocation<'_>, name: &str) -> CommandResult {
+    let guild_id = match inv.guild_id {
+        Some(guild_id) => guild_id.0,
+        None => {
+            inv.reply("The medicus only takes denarii kept in a server.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let glad = match find_character(
+        name,
+        inv.guild_id.map(|guild_id| guild_id.0),
+        inv.author.id.0,
+    )? {
+        Some(glad) if !is_owner(&glad, inv.author.id.0) => {
+            let out = format!("{} does not fight for you!", glad.name);
+            inv.reply(&out).await?;
+            return Ok(());
+        }
+        Some(glad) if glad.dead => {
+            let out = format!("{} is beyond any medicus's help.", glad.name);
+            inv.reply(&out).await?;
+            return Ok(());
+        }
+        Some(glad) => glad,
+        None => {
+            inv.reply("No gladiator goes by that name.").await?;
+            return Ok(());
+        }
+    };
+
+    let injury = glad
+        .injuries
+        .iter()
+        .find(|injury| injury.curable())
+        .copied();
+    let cure_injury = match (glad.wounds > 0 || glad.wounded_until > now(), injury) {
+        (true, _) => None,
+        (false, Some(injury)) => Some(injury),
+        (false, None) => {
+            let out = format!("{} has no need of the medicus.", glad.name);
+            inv.reply(&out).await?;
+            return Ok(());
+        }
+    };
+    let fee = match cure_injury {
+        Some(_) => CURE_FEE,
+        None => TREATMENT_FEE,
+    };
+    if !purse::debit(guild_id, inv.author.id.0, fee)? {
+        let out = format!("The medicus wants {} denarii, which you don't have.", fee);
+        inv.reply(&out).await?;
+        return Ok(());
+    }
+
+    let out = match cure_injury {
+        None => {
+            let healing = roller(2, 4) + glad.level;
+            stitch(&glad, healing)?;
+            format!(
+                "The medicus stitches {} up for {} denarii, restoring {} HP. They're fit to fight.",
+                glad.name,
+                fee,
+                healing.min(glad.wounds)
+            )
+        }
+        Some(injury) => {
+            cure(&glad, injury)?;
+            format!(
+                "The medicus sees to {}'s {} for {} denarii. It troubles them no more.",
+                glad.name,
+                injury.as_str(),
+                fee
+            )
+        }
+    };
+    inv.reply(&out).await?;
+
+    Ok(())
+}
+
+fn stitch(glad: &Character, healing: i8) -> Result<()> {
+    let db = open_db()?;
+
+    db.execute(
+        "UPDATE glads SET wounds = MAX(0, wounds - ?1), wounded_until = MIN(wounded_until, ?2)
+        WHERE id = ?3",
+        params![healing, now(), glad.id],
+    )?;
+
+    Ok(())
+}
+
+fn cure(glad: &Character, injury: Injury) -> Result<()> {
+    let remaining: Vec<&str> = glad
+        .injuries
+        .iter()
+        .filter(|other| **other != injury)
+        .map(|other| other.as_str())
+        .collect();
+    let db = open_db()?;
+
+    db.execute(
+        "UPDATE glads SET injuries = ?1 WHERE id = ?2",
+        params![remaining.join("; "), glad.id],
+    )?;
+
+    Ok(())
+}
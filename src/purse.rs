@@ -0,0 +1,111 @@
+use serenity::builder::CreateApplicationCommands;
+use serenity::client::Context;
+use serenity::framework::standard::{
+    macros::{command, group},
+    CommandResult,
+};
+use serenity::model::channel::Message;
+
+use rusqlite::{params, Connection, Result};
+
+use crate::open_db;
+use crate::slash::Invocation;
+
+// What every player starts out with in each server
+const STARTING_DENARII: i64 = 100;
+
+#[group]
+#[commands(purse)]
+struct Purses;
+
+fn open() -> Result<Connection> {
+    let db = open_db()?;
+
+    match db.execute_batch(
+        "
+    CREATE TABLE IF NOT EXISTS purses (
+        guild_id INTEGER,
+        owner_id INTEGER,
+        denarii INTEGER,
+        PRIMARY KEY (guild_id, owner_id)
+    );",
+    ) {
+        Ok(result) => result,
+        Err(e) => {
+            println!("error creating purse table: {}", e);
+            return Err(e);
+        }
+    };
+
+    Ok(db)
+}
+
+pub fn balance(guild_id: u64, owner_id: u64) -> Result<i64> {
+    let db = open()?;
+
+    db.execute(
+        "INSERT OR IGNORE INTO purses VALUES (?1, ?2, ?3)",
+        params![guild_id, owner_id, STARTING_DENARII],
+    )?;
+    db.query_row(
+        "SELECT denarii FROM purses WHERE guild_id = ?1 AND owner_id = ?2",
+        params![guild_id, owner_id],
+        |row| row.get(0),
+    )
+}
+
+pub fn credit(guild_id: u64, owner_id: u64, denarii: i64) -> Result<()> {
+    balance(guild_id, owner_id)?;
+    let db = open()?;
+
+    db.execute(
+        "UPDATE purses SET denarii = denarii + ?1 WHERE guild_id = ?2 AND owner_id = ?3",
+        params![denarii, guild_id, owner_id],
+    )?;
+
+    Ok(())
+}
+
+// Takes the money if they have it, returning whether they did
+pub fn debit(guild_id: u64, owner_id: u64, denarii: i64) -> Result<bool> {
+    balance(guild_id, owner_id)?;
+    let db = open()?;
+
+    let updated = db.execute(
+        "UPDATE purses SET denarii = denarii - ?1
+        WHERE guild_id = ?2 AND owner_id = ?3 AND denarii >= ?1",
+        params![denarii, guild_id, owner_id],
+    )?;
+
+    Ok(updated == 1)
+}
+
+pub fn register(commands: &mut CreateApplicationCommands) -> &mut CreateApplicationCommands {
+    commands.create_application_command(|command| {
+        command
+            .name("purse")
+            .description("Count the denarii in your purse")
+    })
+}
+
+pub async fn run(inv: &Invocation<'_>) -> CommandResult {
+    show_purse(inv).await
+}
+
+#[command]
+async fn purse(ctx: &Context, msg: &Message) -> CommandResult {
+    show_purse(&Invocation::from_message(ctx, msg)).await
+}
+
+async fn show_purse(inv: &Invocation<'_>) -> CommandResult {
+    let out = match inv.guild_id {
+        Some(guild_id) => format!(
+            "You have {} denarii.",
+            balance(guild_id.0, inv.author.id.0)?
+        ),
+        None => "Purses are only kept in servers.".to_string(),
+    };
+    inv.reply(&out).await?;
+
+    Ok(())
+}
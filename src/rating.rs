@@ -0,0 +1,369 @@
+use serenity::builder::CreateApplicationCommands;
+use serenity::client::Context;
+use serenity::framework::standard::{
+    macros::{command, group},
+    Args, CommandResult,
+};
+use serenity::model::application::command::CommandOptionType;
+use serenity::model::application::interaction::application_command::CommandDataOption;
+use serenity::model::channel::Message;
+
+use rusqlite::{params, Connection, OptionalExtension, Result};
+
+use crate::slash::{gladiator_option, option_int, option_str, Invocation};
+use crate::{find_character, now, open_db, Character};
+
+pub const START_RATING: f64 = 1500.0;
+const K_FACTOR: f64 = 32.0;
+const PAGE_SIZE: i64 = 10;
+const HISTORY_LENGTH: i64 = 20;
+
+#[group]
+#[commands(leaderboard, rank)]
+struct Ratings;
+
+fn open() -> Result<Connection> {
+    let db = open_db()?;
+
+    match db.execute_batch(
+        "
+    CREATE TABLE IF NOT EXISTS owners (
+        guild_id INTEGER,
+        owner_id INTEGER,
+        name STRING,
+        rating REAL NOT NULL DEFAULT 1500,
+        PRIMARY KEY (guild_id, owner_id)
+    );
+    CREATE TABLE IF NOT EXISTS rating_history (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        subject STRING,
+        subject_id INTEGER,
+        guild_id INTEGER,
+        rating REAL,
+        delta REAL,
+        fought_at INTEGER
+    );",
+    ) {
+        Ok(result) => result,
+        Err(e) => {
+            println!("error creating rating tables: {}", e);
+            return Err(e);
+        }
+    };
+
+    Ok(db)
+}
+
+// Chance that a side rated `rating` beats one rated `opponent`
+fn expected(rating: f64, opponent: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf((opponent - rating) / 400.0))
+}
+
+// How far the winner's rating moves, the loser drops by the same amount
+fn elo_delta(winner: f64, loser: f64) -> f64 {
+    K_FACTOR * (1.0 - expected(winner, loser))
+}
+
+fn log_history(
+    db: &Connection,
+    subject: &str,
+    subject_id: u64,
+    guild_id: Option<u64>,
+    rating: f64,
+    delta: f64,
+) -> Result<()> {
+    db.execute(
+        "INSERT INTO rating_history VALUES (NULL, ?1, ?2, ?3, ?4, ?5, ?6)",
+        params![subject, subject_id, guild_id, rating, delta, now()],
+    )?;
+
+    Ok(())
+}
+
+pub fn register_owner(guild_id: u64, owner_id: u64, name: &str) -> Result<()> {
+    let db = open()?;
+
+    db.execute(
+        "INSERT INTO owners (guild_id, owner_id, name) VALUES (?1, ?2, ?3)
+        ON CONFLICT (guild_id, owner_id) DO UPDATE SET name = ?3",
+        params![guild_id, owner_id, name],
+    )?;
+
+    Ok(())
+}
+
+// Moves both gladiators' ratings after a fight, and their owners' if the
+// fight was between two different players in the same server
+pub fn update(winner: &Character, loser: &Character) -> Result<()> {
+    let db = open()?;
+
+    let rating_of = |id: i64| -> Result<f64> {
+        db.query_row("SELECT rating FROM glads WHERE id = ?1", [id], |row| {
+            row.get(0)
+        })
+    };
+    let winner_rating = rating_of(winner.id)?;
+    let loser_rating = rating_of(loser.id)?;
+    let delta = elo_delta(winner_rating, loser_rating);
+
+    for (glad, rating) in [
+        (winner, winner_rating + delta),
+        (loser, loser_rating - delta),
+    ] {
+        db.execute(
+            "UPDATE glads SET rating = ?1 WHERE id = ?2",
+            params![rating, glad.id],
+        )?;
+        let change = if glad.id == winner.id { delta } else { -delta };
+        log_history(&db, "glad", glad.id as u64, glad.guild_id, rating, change)?;
+    }
+
+    let owners = (winner.owner_id, loser.owner_id, winner.guild_id);
+    if let (Some(winner_owner), Some(loser_owner), Some(guild_id)) = owners {
+        if winner_owner == loser_owner || loser.guild_id != Some(guild_id) {
+            return Ok(());
+        }
+
+        let owner_rating_of = |owner_id: u64| -> Result<f64> {
+            let rating = db
+                .query_row(
+                    "SELECT rating FROM owners WHERE guild_id = ?1 AND owner_id = ?2",
+                    params![guild_id, owner_id],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            Ok(rating.unwrap_or(START_RATING))
+        };
+        let winner_rating = owner_rating_of(winner_owner)?;
+        let loser_rating = owner_rating_of(loser_owner)?;
+        let delta = elo_delta(winner_rating, loser_rating);
+
+        for (owner_id, rating, change) in [
+            (winner_owner, winner_rating + delta, delta),
+            (loser_owner, loser_rating - delta, -delta),
+        ] {
+            db.execute(
+                "INSERT INTO owners (guild_id, owner_id, name, rating) VALUES (?1, ?2, 'Unknown', ?3)
+                ON CONFLICT (guild_id, owner_id) DO UPDATE SET rating = ?3",
+                params![guild_id, owner_id, rating],
+            )?;
+            log_history(&db, "owner", owner_id, Some(guild_id), rating, change)?;
+        }
+    }
+
+    Ok(())
+}
+
+// Wins and losses, counted from the fights that moved the gladiator's rating
+pub fn get_record(glad_id: i64) -> Result<(i64, i64)> {
+    let db = open()?;
+
+    db.query_row(
+        "SELECT COUNT(CASE WHEN delta > 0 THEN 1 END), COUNT(CASE WHEN delta < 0 THEN 1 END)
+        FROM rating_history WHERE subject = 'glad' AND subject_id = ?1",
+        [glad_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+}
+
+// Oldest to newest, for charting
+fn get_history(db: &Connection, subject: &str, subject_id: u64) -> Result<Vec<f64>> {
+    let mut stmt = db.prepare(
+        "SELECT rating FROM (
+            SELECT id, rating FROM rating_history WHERE subject = ?1 AND subject_id = ?2
+            ORDER BY id DESC LIMIT ?3
+        ) ORDER BY id ASC",
+    )?;
+    let rows = stmt.query_map(params![subject, subject_id, HISTORY_LENGTH], |row| {
+        row.get(0)
+    })?;
+    rows.collect()
+}
+
+fn get_page(db: &Connection, table: &str, guild_id: u64, page: i64) -> Result<Vec<(String, f64)>> {
+    let mut stmt = db.prepare(&format!(
+        "SELECT name, rating FROM {} WHERE guild_id = ?1 ORDER BY rating DESC LIMIT ?2 OFFSET ?3",
+        table
+    ))?;
+    let rows = stmt.query_map(
+        params![guild_id, PAGE_SIZE, (page - 1) * PAGE_SIZE],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+    rows.collect()
+}
+
+fn sparkline(history: &[f64]) -> String {
+    let bars = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let min = history.iter().cloned().fold(f64::MAX, f64::min);
+    let max = history.iter().cloned().fold(f64::MIN, f64::max);
+    let range = (max - min).max(1.0);
+
+    history
+        .iter()
+        .map(|rating| bars[(((rating - min) / range) * 7.0).round() as usize])
+        .collect()
+}
+
+pub fn register(commands: &mut CreateApplicationCommands) -> &mut CreateApplicationCommands {
+    commands
+        .create_application_command(|command| {
+            command
+                .name("leaderboard")
+                .description("The best rated gladiators, or lanistae, in this server")
+                .create_option(|option| {
+                    option
+                        .name("board")
+                        .description("Which leaderboard to show")
+                        .kind(CommandOptionType::String)
+                        .add_string_choice("Gladiators", "gladiators")
+                        .add_string_choice("Lanistae", "owners")
+                })
+                .create_option(|option| {
+                    option
+                        .name("page")
+                        .description("Page to show")
+                        .kind(CommandOptionType::Integer)
+                        .min_int_value(1)
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("rank")
+                .description("A gladiator's rating and recent form")
+                .create_option(|option| gladiator_option(option, "Gladiator to rank", true))
+        })
+}
+
+pub async fn run(inv: &Invocation<'_>, name: &str, options: &[CommandDataOption]) -> CommandResult {
+    match name {
+        "leaderboard" => {
+            let owners = option_str(options, "board") == Some("owners");
+            let page = option_int(options, "page").unwrap_or(1);
+            show_leaderboard(inv, owners, page).await
+        }
+        "rank" => show_rank(inv, option_str(options, "gladiator").unwrap_or("")).await,
+        _ => Ok(()),
+    }
+}
+
+#[command]
+async fn leaderboard(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let mut owners = false;
+    let mut page = 1;
+    for arg in args.raw() {
+        match arg.parse::<i64>() {
+            Ok(number) => page = number.max(1),
+            Err(_) if arg.eq_ignore_ascii_case("owners") => owners = true,
+            Err(_) => {}
+        }
+    }
+
+    show_leaderboard(&Invocation::from_message(ctx, msg), owners, page).await
+}
+
+async fn show_leaderboard(inv: &Invocation<'_>, owners: bool, page: i64) -> CommandResult {
+    let guild_id = match inv.guild_id {
+        Some(guild_id) => guild_id.0,
+        None => {
+            inv.reply("Leaderboards are only kept in servers.").await?;
+            return Ok(());
+        }
+    };
+
+    let db = open()?;
+    let (title, table) = match owners {
+        true => ("Lanistae", "owners"),
+        false => ("Gladiators", "glads"),
+    };
+    let total: i64 = db.query_row(
+        &format!("SELECT COUNT(*) FROM {} WHERE guild_id = ?1", table),
+        [guild_id],
+        |row| row.get(0),
+    )?;
+    let pages = ((total + PAGE_SIZE - 1) / PAGE_SIZE).max(1);
+    let page = page.clamp(1, pages);
+
+    let rows = get_page(&db, table, guild_id, page)?;
+
+    let mut out = format!("**{}**, page {} of {}\n", title, page, pages);
+    for (i, (name, rating)) in rows.iter().enumerate() {
+        out.push_str(&format!(
+            "{}. {} ({:.0})\n",
+            (page - 1) * PAGE_SIZE + i as i64 + 1,
+            name,
+            rating
+        ));
+    }
+    if rows.is_empty() {
+        out.push_str("Nobody has fought here yet.");
+    }
+    inv.reply(&out).await?;
+
+    Ok(())
+}
+
+#[command]
+async fn rank(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    show_rank(&Invocation::from_message(ctx, msg), args.rest()).await
+}
+
+async fn show_rank(inv: &Invocation<'_>, name: &str) -> CommandResult {
+    let glad = match find_character(
+        name,
+        inv.guild_id.map(|guild_id| guild_id.0),
+        inv.author.id.0,
+    )? {
+        Some(glad) => glad,
+        None => {
+            inv.reply("No gladiator goes by that name.").await?;
+            return Ok(());
+        }
+    };
+
+    let db = open()?;
+    let mut out = format!("{} is rated {:.0}", glad.name, glad.rating);
+    if let Some(guild_id) = glad.guild_id {
+        let ahead: i64 = db.query_row(
+            "SELECT COUNT(*) FROM glads WHERE guild_id = ?1 AND rating > ?2",
+            params![guild_id, glad.rating],
+            |row| row.get(0),
+        )?;
+        out.push_str(&format!(", ranked #{} in the arena", ahead + 1));
+    }
+
+    let history = get_history(&db, "glad", glad.id as u64)?;
+    if history.len() > 1 {
+        out.push_str(&format!(
+            "\nLast {} fights: {}",
+            history.len(),
+            sparkline(&history)
+        ));
+    }
+    inv.reply(&out).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elo_changes_are_zero_sum() {
+        for (winner, loser) in [(1500.0, 1500.0), (1650.0, 1420.0), (1380.0, 1720.0)] {
+            let gained = elo_delta(winner, loser);
+            // What the loser's own expected score says they should drop
+            let lost = K_FACTOR * (0.0 - expected(loser, winner));
+            assert!(gained > 0.0);
+            assert!((gained + lost).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn an_upset_moves_ratings_further() {
+        let upset = elo_delta(1400.0, 1600.0);
+        let expected_win = elo_delta(1600.0, 1400.0);
+        assert!(upset > expected_win);
+        assert!((elo_delta(1500.0, 1500.0) - K_FACTOR / 2.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,174 @@
+use serenity::builder::CreateApplicationCommands;
+use serenity::client::Context;
+use serenity::framework::standard::{
+    macros::{command, group},
+    Args, CommandResult,
+};
+use serenity::model::application::command::CommandOptionType;
+use serenity::model::application::interaction::application_command::CommandDataOption;
+use serenity::model::channel::Message;
+
+use rusqlite::{params, Result};
+
+use crate::slash::{gladiator_option, option_bool, option_str, Invocation};
+use crate::{award_title, find_character, get_character, is_owner, now, open_db, rating};
+
+// A gladiator earns the wooden sword of freedom with this many wins, or by
+// winning enough fame with the crowd
+const RUDIS_WINS: i64 = 10;
+const RUDIS_FAME: i64 = 15;
+const RUDIS_TITLE: &str = "Holder of the Rudis";
+const TRAINER: &str = "trainer";
+
+#[group]
+#[commands(retire)]
+struct Retirements;
+
+// Called after each win, hands the rudis to a gladiator who has earned it.
+// Returns whether they got it just now
+pub fn check_rudis(glad_id: i64) -> Result<bool> {
+    let glad = get_character(glad_id)?;
+    if glad.rudis {
+        return Ok(false);
+    }
+    let (wins, _) = rating::get_record(glad_id)?;
+    if wins < RUDIS_WINS && glad.fame < RUDIS_FAME {
+        return Ok(false);
+    }
+
+    let db = open_db()?;
+    db.execute("UPDATE glads SET rudis = 1 WHERE id = ?1", [glad_id])?;
+    award_title(glad_id, RUDIS_TITLE)?;
+
+    Ok(true)
+}
+
+// Retired gladiators who stayed on to drill the school's fighters
+pub fn count_trainers(guild_id: u64, owner_id: u64) -> Result<i64> {
+    let db = open_db()?;
+
+    db.query_row(
+        "SELECT COUNT(*) FROM glads WHERE guild_id = ?1 AND owner_id = ?2 AND trainer = 1 AND dead = 0",
+        params![guild_id, owner_id],
+        |row| row.get(0),
+    )
+}
+
+fn set_retired(glad_id: i64, trainer: bool) -> Result<()> {
+    let db = open_db()?;
+
+    db.execute(
+        "UPDATE glads SET retired = 1, retired_at = ?1, trainer = ?2 WHERE id = ?3",
+        params![now(), trainer, glad_id],
+    )?;
+
+    Ok(())
+}
+
+pub fn register(commands: &mut CreateApplicationCommands) -> &mut CreateApplicationCommands {
+    commands.create_application_command(|command| {
+        command
+            .name("retire")
+            .description("Retire a gladiator from the arena for good")
+            .create_option(|option| gladiator_option(option, "Your gladiator", true))
+            .create_option(|option| {
+                option
+                    .name(TRAINER)
+                    .description("Keep a holder of the rudis on as a trainer in your ludus")
+                    .kind(CommandOptionType::Boolean)
+            })
+    })
+}
+
+pub async fn run(inv: &Invocation<'_>, options: &[CommandDataOption]) -> CommandResult {
+    let name = option_str(options, "gladiator").unwrap_or("");
+    let trainer = option_bool(options, TRAINER).unwrap_or(false);
+    retire_gladiator(inv, name, trainer).await
+}
+
+#[command]
+async fn retire(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    // Ending with "trainer" keeps them on at the ludus
+    let mut words: Vec<&str> = args.raw().collect();
+    let trainer = words
+        .last()
+        .is_some_and(|word| word.eq_ignore_ascii_case(TRAINER));
+    if trainer {
+        words.pop();
+    }
+    retire_gladiator(
+        &Invocation::from_message(ctx, msg),
+        &words.join(" "),
+        trainer,
+    )
+    .await
+}
+
+async fn retire_gladiator(inv: &Invocation<'_>, name: &str, trainer: bool) -> CommandResult {
+    let glad = match find_character(
+        name,
+        inv.guild_id.map(|guild_id| guild_id.0),
+        inv.author.id.0,
+    )? {
+        Some(glad) if !is_owner(&glad, inv.author.id.0) => {
+            let out = format!("{} does not fight for you!", glad.name);
+            inv.reply(&out).await?;
+            return Ok(());
+        }
+        Some(glad) if glad.dead => {
+            let out = format!("{} already left the arena, feet first.", glad.name);
+            inv.reply(&out).await?;
+            return Ok(());
+        }
+        Some(glad) if glad.retired => {
+            let out = format!("{} has already retired.", glad.name);
+            inv.reply(&out).await?;
+            return Ok(());
+        }
+        Some(glad) => glad,
+        None => {
+            inv.reply("No gladiator goes by that name.").await?;
+            return Ok(());
+        }
+    };
+
+    // Only the free can choose to stay, and a trainer needs a ludus to train in
+    if trainer && !glad.rudis {
+        let out = format!(
+            "Only a holder of the rudis can stay on as a trainer, {} needs {} more wins or {} more fame.",
+            glad.name,
+            (RUDIS_WINS - rating::get_record(glad.id)?.0).max(0),
+            (RUDIS_FAME - glad.fame).max(0)
+        );
+        inv.reply(&out).await?;
+        return Ok(());
+    }
+    if trainer && (glad.guild_id.is_none() || glad.owner_id.is_none()) {
+        let out = format!(
+            "{} belongs to no ludus in this server, so there's nowhere for them to train.",
+            glad.name
+        );
+        inv.reply(&out).await?;
+        return Ok(());
+    }
+
+    set_retired(glad.id, trainer)?;
+    let (wins, losses) = rating::get_record(glad.id)?;
+    let out = match (glad.rudis, trainer) {
+        (true, true) => format!(
+            "{} hangs up their arms with the rudis in hand, a free gladiator, and stays on to drill your ludus. ({} wins, {} losses)",
+            glad.name, wins, losses
+        ),
+        (true, false) => format!(
+            "{} walks out of the arena a free gladiator, the rudis in hand. ({} wins, {} losses)",
+            glad.name, wins, losses
+        ),
+        _ => format!(
+            "{} retires from the arena. ({} wins, {} losses)",
+            glad.name, wins, losses
+        ),
+    };
+    inv.reply(&out).await?;
+
+    Ok(())
+}
@@ -0,0 +1,356 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use serenity::builder::{CreateApplicationCommandOption, CreateApplicationCommands, CreateEmbed};
+use serenity::client::Context;
+use serenity::framework::standard::CommandResult;
+use serenity::model::application::command::{Command, CommandOptionType};
+use serenity::model::application::interaction::application_command::{
+    ApplicationCommandInteraction, CommandDataOption, CommandDataOptionValue,
+};
+use serenity::model::application::interaction::autocomplete::AutocompleteInteraction;
+use serenity::model::application::interaction::{Interaction, InteractionResponseType};
+use serenity::model::channel::Message;
+use serenity::model::id::{ChannelId, GuildId};
+use serenity::model::user::User;
+
+use rusqlite::{params, Result};
+
+use crate::combat::Profile;
+use crate::{
+    challenge, create_glad, funnel, hall, league, ludus, market, medicus, open_db, purse, rating,
+    retirement, set_profile, show_sheet, start_fight, taunt_player, tournament, training,
+};
+
+// Autocomplete can offer at most this many choices
+const MAX_CHOICES: usize = 25;
+
+// Whoever asked for a command and how to answer them, so the same command
+// can be run from a prefix message or a slash command
+pub struct Invocation<'a> {
+    pub ctx: &'a Context,
+    pub author: &'a User,
+    pub channel_id: ChannelId,
+    pub guild_id: Option<GuildId>,
+    source: Source<'a>,
+    responded: AtomicBool,
+}
+
+enum Source<'a> {
+    Message(&'a Message),
+    Interaction(&'a ApplicationCommandInteraction),
+}
+
+impl<'a> Invocation<'a> {
+    pub fn from_message(ctx: &'a Context, msg: &'a Message) -> Invocation<'a> {
+        Invocation {
+            ctx,
+            author: &msg.author,
+            channel_id: msg.channel_id,
+            guild_id: msg.guild_id,
+            source: Source::Message(msg),
+            responded: AtomicBool::new(false),
+        }
+    }
+
+    pub fn from_interaction(
+        ctx: &'a Context,
+        command: &'a ApplicationCommandInteraction,
+    ) -> Invocation<'a> {
+        Invocation {
+            ctx,
+            author: &command.user,
+            channel_id: command.channel_id,
+            guild_id: command.guild_id,
+            source: Source::Interaction(command),
+            responded: AtomicBool::new(false),
+        }
+    }
+
+    pub async fn reply(&self, content: &str) -> serenity::Result<()> {
+        self.send(content, None).await?;
+        Ok(())
+    }
+
+    pub async fn reply_embed(&self, embed: CreateEmbed) -> serenity::Result<Message> {
+        self.send("", Some(embed)).await
+    }
+
+    // An interaction has to be answered once before anything can follow up,
+    // either way hands back the message so it can be edited later
+    async fn send(&self, content: &str, embed: Option<CreateEmbed>) -> serenity::Result<Message> {
+        match self.source {
+            Source::Message(msg) => {
+                msg.channel_id
+                    .send_message(&self.ctx.http, |message| {
+                        message.reference_message(msg);
+                        if !content.is_empty() {
+                            message.content(content);
+                        }
+                        if let Some(embed) = embed {
+                            message.set_embed(embed);
+                        }
+                        message
+                    })
+                    .await
+            }
+            Source::Interaction(command) => {
+                if self.responded.swap(true, Ordering::SeqCst) {
+                    command
+                        .create_followup_message(&self.ctx.http, |message| {
+                            if !content.is_empty() {
+                                message.content(content);
+                            }
+                            if let Some(embed) = embed {
+                                message.set_embed(embed);
+                            }
+                            message
+                        })
+                        .await
+                } else {
+                    command
+                        .create_interaction_response(&self.ctx.http, |response| {
+                            response
+                                .kind(InteractionResponseType::ChannelMessageWithSource)
+                                .interaction_response_data(|message| {
+                                    if !content.is_empty() {
+                                        message.content(content);
+                                    }
+                                    if let Some(embed) = embed {
+                                        message.set_embed(embed);
+                                    }
+                                    message
+                                })
+                        })
+                        .await?;
+                    command.get_interaction_response(&self.ctx.http).await
+                }
+            }
+        }
+    }
+}
+
+fn find_option<'a>(options: &'a [CommandDataOption], name: &str) -> Option<&'a CommandDataOption> {
+    options.iter().find(|option| option.name == name)
+}
+
+pub fn option_str<'a>(options: &'a [CommandDataOption], name: &str) -> Option<&'a str> {
+    find_option(options, name)?.value.as_ref()?.as_str()
+}
+
+pub fn option_int(options: &[CommandDataOption], name: &str) -> Option<i64> {
+    find_option(options, name)?.value.as_ref()?.as_i64()
+}
+
+pub fn option_bool(options: &[CommandDataOption], name: &str) -> Option<bool> {
+    find_option(options, name)?.value.as_ref()?.as_bool()
+}
+
+pub fn option_user<'a>(options: &'a [CommandDataOption], name: &str) -> Option<&'a User> {
+    match &find_option(options, name)?.resolved {
+        Some(CommandDataOptionValue::User(user, _)) => Some(user),
+        _ => None,
+    }
+}
+
+// The subcommand picked and its own options, for commands like /tournament
+pub fn subcommand(options: &[CommandDataOption]) -> Option<(&str, &[CommandDataOption])> {
+    let subcommand = options.first()?;
+    Some((subcommand.name.as_str(), &subcommand.options))
+}
+
+// A gladiator name option that suggests names as it's typed
+pub fn gladiator_option<'a>(
+    option: &'a mut CreateApplicationCommandOption,
+    description: &str,
+    required: bool,
+) -> &'a mut CreateApplicationCommandOption {
+    option
+        .name("gladiator")
+        .description(description)
+        .kind(CommandOptionType::String)
+        .required(required)
+        .set_autocomplete(true)
+}
+
+// Lets the owners call each round's action with buttons
+pub fn interactive_option(
+    option: &mut CreateApplicationCommandOption,
+) -> &mut CreateApplicationCommandOption {
+    option
+        .name("interactive")
+        .description("Choose your gladiator's action each round")
+        .kind(CommandOptionType::Boolean)
+}
+
+fn register_general(commands: &mut CreateApplicationCommands) -> &mut CreateApplicationCommands {
+    commands
+        .create_application_command(|command| {
+            command
+                .name("glad")
+                .description("Send a new gladiator into the arena")
+        })
+        .create_application_command(|command| {
+            command
+                .name("sheet")
+                .description("Show a gladiator's character sheet")
+                .create_option(|option| gladiator_option(option, "Gladiator to show", true))
+        })
+        .create_application_command(|command| {
+            command
+                .name("profile")
+                .description("Set how one of your gladiators fights when left to their own devices")
+                .create_option(|option| gladiator_option(option, "Your gladiator", true))
+                .create_option(|option| {
+                    option
+                        .name("profile")
+                        .description("How they fight")
+                        .kind(CommandOptionType::String)
+                        .required(true);
+                    for profile in Profile::ALL {
+                        option.add_string_choice(profile.as_str(), profile.as_str());
+                    }
+                    option
+                })
+        })
+        .create_application_command(|command| {
+            command
+                .name("taunt")
+                .description("Have the crowd taunt you")
+        })
+        .create_application_command(|command| {
+            command
+                .name("fight")
+                .description("Send a gladiator to fight to the death against a worthy opponent")
+                .create_option(|option| {
+                    gladiator_option(option, "Your gladiator, or leave it to the lanista", false)
+                })
+                .create_option(interactive_option)
+        })
+}
+
+pub async fn register(ctx: &Context) {
+    let registered = Command::set_global_application_commands(&ctx.http, |commands| {
+        register_general(commands);
+        tournament::register(commands);
+        league::register(commands);
+        rating::register(commands);
+        purse::register(commands);
+        challenge::register(commands);
+        medicus::register(commands);
+        training::register(commands);
+        ludus::register(commands);
+        market::register(commands);
+        funnel::register(commands);
+        retirement::register(commands);
+        hall::register(commands)
+    })
+    .await;
+
+    if let Err(e) = registered {
+        println!("error registering slash commands: {}", e);
+    }
+}
+
+pub async fn handle(ctx: &Context, interaction: Interaction) {
+    match interaction {
+        Interaction::ApplicationCommand(command) => run(ctx, &command).await,
+        Interaction::Autocomplete(autocomplete) => suggest(ctx, &autocomplete).await,
+        _ => {}
+    }
+}
+
+async fn run(ctx: &Context, command: &ApplicationCommandInteraction) {
+    let inv = Invocation::from_interaction(ctx, command);
+    let name = command.data.name.as_str();
+    let options = &command.data.options;
+    println!("{} used /{}", inv.author.name, name);
+
+    let result: CommandResult = match name {
+        "glad" => create_glad(&inv).await,
+        "sheet" => show_sheet(&inv, option_str(options, "gladiator").unwrap_or("")).await,
+        "profile" => {
+            let name = option_str(options, "gladiator").unwrap_or("");
+            set_profile(&inv, name, option_str(options, "profile").unwrap_or("")).await
+        }
+        "taunt" => taunt_player(&inv).await,
+        "fight" => {
+            let name = option_str(options, "gladiator").unwrap_or("");
+            let interactive = option_bool(options, "interactive").unwrap_or(false);
+            start_fight(&inv, name, interactive).await
+        }
+        "tournament" => tournament::run(&inv, options).await,
+        "league" => league::run(&inv, options).await,
+        "leaderboard" | "rank" => rating::run(&inv, name, options).await,
+        "purse" => purse::run(&inv).await,
+        "challenge" | "accept" | "decline" => challenge::run(&inv, name, options).await,
+        "medicus" => medicus::run(&inv, options).await,
+        "train" => training::run(&inv, options).await,
+        "ludus" => ludus::run(&inv, options).await,
+        "market" => market::run(&inv, options).await,
+        "funnel" => funnel::run(&inv).await,
+        "retire" => retirement::run(&inv, options).await,
+        "hof" => hall::run(&inv, options).await,
+        _ => Ok(()),
+    };
+
+    if let Err(e) = result {
+        println!("error running /{}: {}", name, e);
+    }
+}
+
+fn focused(options: &[CommandDataOption]) -> Option<&CommandDataOption> {
+    options.iter().find_map(|option| match option.focused {
+        true => Some(option),
+        false => focused(&option.options),
+    })
+}
+
+// Living gladiators the player owns come first, then everyone else's
+fn suggest_gladiators(guild_id: Option<u64>, user_id: u64, typed: &str) -> Result<Vec<String>> {
+    let db = open_db()?;
+
+    // Whatever was typed is matched literally, wildcards and all
+    let typed = typed
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    // Those still fighting come first, the retired and then the dead after
+    let mut stmt = db.prepare(
+        "SELECT name FROM glads
+        WHERE name LIKE ?1 || '%' ESCAPE '\\' AND (?2 IS NULL OR guild_id IS NULL OR guild_id = ?2)
+        ORDER BY dead ASC, retired ASC, owner_id IS ?3 DESC, rating DESC LIMIT ?4",
+    )?;
+    let rows = stmt.query_map(
+        params![typed, guild_id, user_id, MAX_CHOICES as i64],
+        |row| row.get(0),
+    )?;
+    rows.collect()
+}
+
+async fn suggest(ctx: &Context, autocomplete: &AutocompleteInteraction) {
+    let typed = focused(&autocomplete.data.options)
+        .and_then(|option| option.value.as_ref())
+        .and_then(|value| value.as_str())
+        .unwrap_or("");
+    let guild_id = autocomplete.guild_id.map(|guild_id| guild_id.0);
+
+    let names = match suggest_gladiators(guild_id, autocomplete.user.id.0, typed) {
+        Ok(names) => names,
+        Err(e) => {
+            println!("error suggesting gladiators: {}", e);
+            return;
+        }
+    };
+
+    let responded = autocomplete
+        .create_autocomplete_response(&ctx.http, |response| {
+            for name in names.iter() {
+                response.add_string_choice(name, name);
+            }
+            response
+        })
+        .await;
+    if let Err(e) = responded {
+        println!("error sending suggestions: {}", e);
+    }
+}
@@ -0,0 +1,720 @@
+use serenity::builder::CreateApplicationCommands;
+use serenity::client::Context;
+use serenity::framework::standard::{
+    macros::{command, group},
+    Args, CommandResult,
+};
+use serenity::model::application::command::CommandOptionType;
+use serenity::model::application::interaction::application_command::CommandDataOption;
+use serenity::model::channel::Message;
+use serenity::model::id::ChannelId;
+
+use rusqlite::{params, Connection, OptionalExtension, Result, Row};
+
+use crate::slash::{gladiator_option, option_int, option_str, subcommand, Invocation};
+use crate::{award_title, combat, find_character, get_character, in_guild, is_owner, now, open_db};
+
+const DEFAULT_ROUND_MINUTES: i64 = 10;
+
+#[group]
+#[commands(tournament)]
+struct Tournaments;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Single,
+    Double,
+}
+
+impl Format {
+    fn parse(format: &str) -> Option<Format> {
+        match format.to_lowercase().as_str() {
+            "single" => Some(Format::Single),
+            "double" => Some(Format::Double),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Format::Single => "single",
+            Format::Double => "double",
+        }
+    }
+
+    // A gladiator is knocked out once they've lost this many matches
+    fn max_losses(&self) -> i64 {
+        match self {
+            Format::Single => 1,
+            Format::Double => 2,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Tournament {
+    id: i64,
+    channel_id: u64,
+    name: String,
+    format: Format,
+    status: String,
+    round: i64,
+    round_minutes: i64,
+}
+
+#[derive(Clone, Debug)]
+struct Entry {
+    glad_id: i64,
+    name: String,
+    seed: i64,
+    losses: i64,
+}
+
+fn open() -> Result<Connection> {
+    let db = open_db()?;
+
+    match db.execute_batch(
+        "
+    CREATE TABLE IF NOT EXISTS tournaments (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        channel_id INTEGER,
+        name STRING,
+        format STRING,
+        status STRING,
+        round INTEGER,
+        round_minutes INTEGER,
+        next_round_at INTEGER,
+        champion_id INTEGER
+    );
+    CREATE TABLE IF NOT EXISTS tournament_entries (
+        tournament_id INTEGER,
+        glad_id INTEGER,
+        seed INTEGER,
+        losses INTEGER,
+        PRIMARY KEY (tournament_id, glad_id)
+    );
+    CREATE TABLE IF NOT EXISTS tournament_matches (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        tournament_id INTEGER,
+        round INTEGER,
+        glad1_id INTEGER,
+        glad2_id INTEGER,
+        winner_id INTEGER
+    );",
+    ) {
+        Ok(result) => result,
+        Err(e) => {
+            println!("error creating tournament tables: {}", e);
+            return Err(e);
+        }
+    };
+
+    Ok(db)
+}
+
+fn row_to_tournament(row: &Row) -> Result<Tournament> {
+    let format: String = row.get(3)?;
+    Ok(Tournament {
+        id: row.get(0)?,
+        channel_id: row.get(1)?,
+        name: row.get(2)?,
+        format: Format::parse(&format).unwrap_or(Format::Single),
+        status: row.get(4)?,
+        round: row.get(5)?,
+        round_minutes: row.get(6)?,
+    })
+}
+
+// Each channel runs at most one tournament at a time
+fn get_current(db: &Connection, channel_id: u64) -> Result<Option<Tournament>> {
+    db.query_row(
+        "SELECT id, channel_id, name, format, status, round, round_minutes FROM tournaments
+        WHERE channel_id = ?1 AND status != 'finished' ORDER BY id DESC LIMIT 1",
+        [channel_id],
+        row_to_tournament,
+    )
+    .optional()
+}
+
+fn get_due(db: &Connection) -> Result<Vec<Tournament>> {
+    let mut stmt = db.prepare(
+        "SELECT id, channel_id, name, format, status, round, round_minutes FROM tournaments
+        WHERE status = 'running' AND next_round_at <= ?1",
+    )?;
+    let rows = stmt.query_map([now()], row_to_tournament)?;
+    rows.collect()
+}
+
+fn get_entries(db: &Connection, tournament_id: i64) -> Result<Vec<Entry>> {
+    let mut stmt = db.prepare(
+        "SELECT e.glad_id, g.name, e.seed, e.losses FROM tournament_entries e
+        JOIN glads g ON g.id = e.glad_id
+        WHERE e.tournament_id = ?1 ORDER BY e.seed",
+    )?;
+    let rows = stmt.query_map([tournament_id], |row| {
+        Ok(Entry {
+            glad_id: row.get(0)?,
+            name: row.get(1)?,
+            seed: row.get(2)?,
+            losses: row.get(3)?,
+        })
+    })?;
+    rows.collect()
+}
+
+// Gladiators still in the running are paired with others on the same number
+// of losses, top seed against bottom seed. Anyone left over gets a bye. When
+// only two remain they meet in the final regardless of bracket, so a losers
+// bracket winner beating the unbeaten finalist forces a deciding rematch.
+fn pair_round(entries: &[Entry], format: Format) -> (Vec<(Entry, Entry)>, Vec<Entry>) {
+    let alive: Vec<&Entry> = entries
+        .iter()
+        .filter(|e| e.losses < format.max_losses())
+        .collect();
+
+    let mut pairs = Vec::new();
+    let mut byes = Vec::new();
+
+    if alive.len() == 2 {
+        pairs.push((alive[0].clone(), alive[1].clone()));
+        return (pairs, byes);
+    }
+
+    for losses in 0..format.max_losses() {
+        let mut bracket: Vec<&Entry> = alive
+            .iter()
+            .filter(|e| e.losses == losses)
+            .copied()
+            .collect();
+        while bracket.len() >= 2 {
+            let top = bracket.remove(0);
+            let bottom = bracket.pop().unwrap();
+            pairs.push((top.clone(), bottom.clone()));
+        }
+        if let Some(bye) = bracket.pop() {
+            byes.push(bye.clone());
+        }
+    }
+
+    (pairs, byes)
+}
+
+// Fights every match of the next round, saves the results and returns the
+// bracket summary to post
+fn run_round(db: &Connection, tournament: &Tournament) -> Result<String> {
+    // Gladiators who died or retired since the last round are out
+    db.execute(
+        "UPDATE tournament_entries SET losses = ?1
+        WHERE tournament_id = ?2 AND glad_id IN (SELECT id FROM glads WHERE dead = 1 OR retired = 1)",
+        params![tournament.format.max_losses(), tournament.id],
+    )?;
+
+    let entries = get_entries(db, tournament.id)?;
+    let (pairs, byes) = pair_round(&entries, tournament.format);
+    let round = tournament.round + 1;
+
+    let mut out = format!("**{}**: Round {}\n", tournament.name, round);
+    for (entry1, entry2) in pairs {
+        let bout = combat::resolve(
+            get_character(entry1.glad_id)?,
+            get_character(entry2.glad_id)?,
+        );
+        combat::record(&bout)?;
+        let winner = bout.winner().unwrap();
+        let loser = bout.loser().unwrap();
+
+        db.execute(
+            "INSERT INTO tournament_matches VALUES (NULL, ?1, ?2, ?3, ?4, ?5)",
+            params![
+                tournament.id,
+                round,
+                entry1.glad_id,
+                entry2.glad_id,
+                winner.id
+            ],
+        )?;
+        db.execute(
+            "UPDATE tournament_entries SET losses = losses + 1 WHERE tournament_id = ?1 AND glad_id = ?2",
+            params![tournament.id, loser.id],
+        )?;
+        out.push_str(&format!(
+            "{} defeated {} after {} rounds\n",
+            winner.name, loser.name, bout.round
+        ));
+    }
+    for bye in byes {
+        out.push_str(&format!("{} advances with a bye\n", bye.name));
+    }
+
+    let entries = get_entries(db, tournament.id)?;
+    let alive: Vec<&Entry> = entries
+        .iter()
+        .filter(|e| e.losses < tournament.format.max_losses())
+        .collect();
+
+    out.push('\n');
+    out.push_str(&bracket_summary(&entries, tournament.format));
+
+    if alive.is_empty() {
+        db.execute(
+            "UPDATE tournaments SET round = ?1, status = 'finished' WHERE id = ?2",
+            params![round, tournament.id],
+        )?;
+        out.push_str(&format!(
+            "\nNobody is left standing to claim {}.",
+            tournament.name
+        ));
+    } else if alive.len() == 1 {
+        db.execute(
+            "UPDATE tournaments SET round = ?1, status = 'finished', champion_id = ?2 WHERE id = ?3",
+            params![round, alive[0].glad_id, tournament.id],
+        )?;
+        award_title(
+            alive[0].glad_id,
+            &format!("Champion of {}", tournament.name),
+        )?;
+        out.push_str(&format!(
+            "\n{} is the champion of {}!",
+            alive[0].name, tournament.name
+        ));
+    } else {
+        db.execute(
+            "UPDATE tournaments SET round = ?1, next_round_at = ?2 WHERE id = ?3",
+            params![round, now() + tournament.round_minutes * 60, tournament.id],
+        )?;
+        out.push_str(&format!(
+            "\nNext round in {} minutes.",
+            tournament.round_minutes
+        ));
+    }
+
+    Ok(out)
+}
+
+fn bracket_summary(entries: &[Entry], format: Format) -> String {
+    let names = |losses: i64| {
+        entries
+            .iter()
+            .filter(|e| e.losses == losses)
+            .map(|e| format!("({}) {}", e.seed, e.name))
+            .collect::<Vec<String>>()
+            .join(", ")
+    };
+    let eliminated = entries
+        .iter()
+        .filter(|e| e.losses >= format.max_losses())
+        .map(|e| e.name.clone())
+        .collect::<Vec<String>>()
+        .join(", ");
+
+    let mut out = match format {
+        Format::Single => format!("Still standing: {}\n", names(0)),
+        Format::Double => format!(
+            "Winners bracket: {}\nLosers bracket: {}\n",
+            names(0),
+            names(1)
+        ),
+    };
+    if !eliminated.is_empty() {
+        out.push_str(&format!("Eliminated: {}\n", eliminated));
+    }
+    out
+}
+
+// Called by the scheduler to play any rounds that have come due
+pub async fn tick(ctx: &Context) {
+    let db = match open() {
+        Ok(db) => db,
+        Err(e) => {
+            println!("error opening tournaments: {}", e);
+            return;
+        }
+    };
+    let due = match get_due(&db) {
+        Ok(due) => due,
+        Err(e) => {
+            println!("error loading tournaments: {}", e);
+            return;
+        }
+    };
+
+    for tournament in due {
+        match run_round(&db, &tournament) {
+            Ok(out) => {
+                if let Err(e) = ChannelId(tournament.channel_id).say(&ctx.http, &out).await {
+                    println!("error posting tournament round: {}", e);
+                }
+            }
+            Err(e) => println!("error running tournament {}: {}", tournament.id, e),
+        }
+    }
+}
+
+pub fn register(commands: &mut CreateApplicationCommands) -> &mut CreateApplicationCommands {
+    commands.create_application_command(|command| {
+        command
+            .name("tournament")
+            .description("Elimination tournaments")
+            .create_option(|option| {
+                option
+                    .name("show")
+                    .description("Show the bracket of this channel's tournament")
+                    .kind(CommandOptionType::SubCommand)
+            })
+            .create_option(|option| {
+                option
+                    .name("create")
+                    .description("Announce a tournament in this channel")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|option| {
+                        option
+                            .name("name")
+                            .description("What the games are called")
+                            .kind(CommandOptionType::String)
+                    })
+                    .create_sub_option(|option| {
+                        option
+                            .name("format")
+                            .description("How many losses knock a gladiator out")
+                            .kind(CommandOptionType::String)
+                            .add_string_choice("Single elimination", "single")
+                            .add_string_choice("Double elimination", "double")
+                    })
+                    .create_sub_option(|option| {
+                        option
+                            .name("minutes")
+                            .description("Minutes between rounds")
+                            .kind(CommandOptionType::Integer)
+                            .min_int_value(0)
+                    })
+            })
+            .create_option(|option| {
+                option
+                    .name("join")
+                    .description("Enter a gladiator")
+                    .kind(CommandOptionType::SubCommand)
+                    .create_sub_option(|option| {
+                        gladiator_option(option, "Gladiator to enter", true)
+                    })
+            })
+            .create_option(|option| {
+                option
+                    .name("start")
+                    .description("Close entries and fight the first round")
+                    .kind(CommandOptionType::SubCommand)
+            })
+    })
+}
+
+pub async fn run(inv: &Invocation<'_>, options: &[CommandDataOption]) -> CommandResult {
+    match subcommand(options) {
+        Some(("create", options)) => {
+            let format = option_str(options, "format")
+                .and_then(Format::parse)
+                .unwrap_or(Format::Single);
+            let round_minutes = option_int(options, "minutes").unwrap_or(DEFAULT_ROUND_MINUTES);
+            let name = option_str(options, "name").unwrap_or("");
+            create_tournament(inv, format, round_minutes.max(0), name).await
+        }
+        Some(("join", options)) => {
+            join_tournament(inv, option_str(options, "gladiator").unwrap_or("")).await
+        }
+        Some(("start", _)) => start_tournament(inv).await,
+        _ => show_tournament(inv).await,
+    }
+}
+
+#[command]
+#[sub_commands(create, join, start)]
+async fn tournament(ctx: &Context, msg: &Message) -> CommandResult {
+    show_tournament(&Invocation::from_message(ctx, msg)).await
+}
+
+async fn show_tournament(inv: &Invocation<'_>) -> CommandResult {
+    let db = open()?;
+    let out = match get_current(&db, inv.channel_id.0)? {
+        Some(tournament) => {
+            let entries = get_entries(&db, tournament.id)?;
+            format!(
+                "**{}** ({} elimination, {}, round {})\n{}",
+                tournament.name,
+                tournament.format.as_str(),
+                tournament.status,
+                tournament.round,
+                bracket_summary(&entries, tournament.format)
+            )
+        }
+        None => "There is no tournament in this channel. Start one with `/tournament create [single|double] [minutes between rounds] <name>`".to_string(),
+    };
+    inv.reply(&out).await?;
+
+    Ok(())
+}
+
+#[command]
+async fn create(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let mut format = Format::Single;
+    let mut round_minutes = DEFAULT_ROUND_MINUTES;
+    let mut name = Vec::new();
+    for arg in args.raw() {
+        if let Some(f) = Format::parse(arg) {
+            format = f;
+        } else if let Ok(minutes) = arg.parse::<i64>() {
+            round_minutes = minutes.max(0);
+        } else {
+            name.push(arg);
+        }
+    }
+
+    create_tournament(
+        &Invocation::from_message(ctx, msg),
+        format,
+        round_minutes,
+        &name.join(" "),
+    )
+    .await
+}
+
+async fn create_tournament(
+    inv: &Invocation<'_>,
+    format: Format,
+    round_minutes: i64,
+    name: &str,
+) -> CommandResult {
+    let db = open()?;
+    if get_current(&db, inv.channel_id.0)?.is_some() {
+        inv.reply("A tournament is already under way in this channel!")
+            .await?;
+        return Ok(());
+    }
+
+    let name = match name {
+        "" => format!("The Games of {}", inv.author.name),
+        name => name.to_string(),
+    };
+
+    db.execute(
+        "INSERT INTO tournaments VALUES (NULL, ?1, ?2, ?3, 'open', 0, ?4, NULL, NULL)",
+        params![inv.channel_id.0, name, format.as_str(), round_minutes],
+    )?;
+    println!("{} created tournament {}", inv.author.name, name);
+
+    let out = format!(
+        "{} has announced **{}**, a {} elimination tournament! Enter your gladiators with `/tournament join <name>`",
+        inv.author.name,
+        name,
+        format.as_str()
+    );
+    inv.reply(&out).await?;
+
+    Ok(())
+}
+
+#[command]
+async fn join(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    join_tournament(&Invocation::from_message(ctx, msg), args.rest()).await
+}
+
+async fn join_tournament(inv: &Invocation<'_>, name: &str) -> CommandResult {
+    let db = open()?;
+    let tournament = match get_current(&db, inv.channel_id.0)? {
+        Some(tournament) if tournament.status == "open" => tournament,
+        _ => {
+            inv.reply("There is no tournament taking entries in this channel.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let glad = match find_character(
+        name,
+        inv.guild_id.map(|guild_id| guild_id.0),
+        inv.author.id.0,
+    )? {
+        Some(glad) if !in_guild(&glad, inv.guild_id.map(|guild_id| guild_id.0)) => {
+            let out = format!("{} fights in another arena.", glad.name);
+            inv.reply(&out).await?;
+            return Ok(());
+        }
+        Some(glad) if !is_owner(&glad, inv.author.id.0) => {
+            let out = format!("{} does not fight for you!", glad.name);
+            inv.reply(&out).await?;
+            return Ok(());
+        }
+        Some(glad) if !glad.dead && !glad.retired => glad,
+        Some(glad) if glad.retired => {
+            let out = format!("{} has retired from the arena.", glad.name);
+            inv.reply(&out).await?;
+            return Ok(());
+        }
+        Some(glad) => {
+            let out = format!("{} is dead and cannot compete.", glad.name);
+            inv.reply(&out).await?;
+            return Ok(());
+        }
+        None => {
+            inv.reply("No gladiator goes by that name.").await?;
+            return Ok(());
+        }
+    };
+
+    let seed = get_entries(&db, tournament.id)?.len() as i64 + 1;
+    let inserted = db.execute(
+        "INSERT OR IGNORE INTO tournament_entries VALUES (?1, ?2, ?3, 0)",
+        params![tournament.id, glad.id, seed],
+    )?;
+    let out = match inserted {
+        0 => format!("{} is already entered in {}", glad.name, tournament.name),
+        _ => format!("{} enters {} as seed {}", glad.name, tournament.name, seed),
+    };
+    inv.reply(&out).await?;
+
+    Ok(())
+}
+
+#[command]
+async fn start(ctx: &Context, msg: &Message) -> CommandResult {
+    start_tournament(&Invocation::from_message(ctx, msg)).await
+}
+
+async fn start_tournament(inv: &Invocation<'_>) -> CommandResult {
+    let db = open()?;
+    let tournament = match get_current(&db, inv.channel_id.0)? {
+        Some(tournament) if tournament.status == "open" => tournament,
+        _ => {
+            inv.reply("There is no tournament waiting to start in this channel.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    if get_entries(&db, tournament.id)?.len() < 2 {
+        inv.reply("A tournament needs at least two gladiators!")
+            .await?;
+        return Ok(());
+    }
+
+    db.execute(
+        "UPDATE tournaments SET status = 'running' WHERE id = ?1",
+        [tournament.id],
+    )?;
+    inv.reply(&format!("Let {} begin!", tournament.name))
+        .await?;
+
+    let out = run_round(&db, &tournament)?;
+    inv.reply(&out).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(count: i64) -> Vec<Entry> {
+        (1..=count)
+            .map(|seed| Entry {
+                glad_id: seed,
+                name: format!("Seed {}", seed),
+                seed,
+                losses: 0,
+            })
+            .collect()
+    }
+
+    fn seeds(pairs: &[(Entry, Entry)]) -> Vec<(i64, i64)> {
+        pairs.iter().map(|(a, b)| (a.seed, b.seed)).collect()
+    }
+
+    // Plays the bracket out with the better seed always winning, checking
+    // every gladiator still in fights or sits out exactly once a round, and
+    // returns the champion's seed and how many rounds it took
+    fn play_out(count: i64, format: Format) -> (i64, usize) {
+        let mut entries = entries(count);
+        let mut rounds = 0;
+        loop {
+            let alive: Vec<i64> = entries
+                .iter()
+                .filter(|e| e.losses < format.max_losses())
+                .map(|e| e.seed)
+                .collect();
+            if alive.len() <= 1 {
+                return (alive[0], rounds);
+            }
+
+            let (pairs, byes) = pair_round(&entries, format);
+            let mut seen: Vec<i64> = pairs
+                .iter()
+                .flat_map(|(a, b)| [a.seed, b.seed])
+                .chain(byes.iter().map(|e| e.seed))
+                .collect();
+            seen.sort();
+            assert_eq!(seen, alive);
+            assert!(!pairs.is_empty());
+
+            for (a, b) in pairs {
+                let loser = a.seed.max(b.seed);
+                entries[loser as usize - 1].losses += 1;
+            }
+            rounds += 1;
+            assert!(rounds <= 3 * count as usize, "the bracket never ends");
+        }
+    }
+
+    #[test]
+    fn single_elimination_pairs_top_against_bottom_with_a_bye() {
+        let (pairs, byes) = pair_round(&entries(5), Format::Single);
+        assert_eq!(seeds(&pairs), vec![(1, 5), (2, 4)]);
+        assert_eq!(byes.iter().map(|e| e.seed).collect::<Vec<_>>(), vec![3]);
+    }
+
+    #[test]
+    fn single_elimination_with_odd_fields_crowns_the_top_seed() {
+        for count in [3, 5, 7, 9] {
+            let (champion, rounds) = play_out(count, Format::Single);
+            assert_eq!(champion, 1);
+            assert!(rounds < count as usize);
+        }
+    }
+
+    #[test]
+    fn double_elimination_keeps_a_losers_bracket() {
+        let mut entries = entries(5);
+        entries[4].losses = 1;
+        entries[3].losses = 1;
+        let (pairs, byes) = pair_round(&entries, Format::Double);
+        assert_eq!(seeds(&pairs), vec![(1, 3), (4, 5)]);
+        assert_eq!(byes.iter().map(|e| e.seed).collect::<Vec<_>>(), vec![2]);
+    }
+
+    #[test]
+    fn double_elimination_with_odd_fields_crowns_the_top_seed() {
+        for count in [3, 5, 7, 9] {
+            let (champion, _) = play_out(count, Format::Double);
+            assert_eq!(champion, 1);
+        }
+    }
+
+    #[test]
+    fn double_elimination_final_is_reset_when_the_unbeaten_side_loses() {
+        let mut entries = entries(2);
+        entries[1].losses = 1;
+        let (pairs, _) = pair_round(&entries, Format::Double);
+        assert_eq!(seeds(&pairs), vec![(1, 2)]);
+
+        // The losers bracket winner takes the first final, so they go again
+        entries[0].losses = 1;
+        let (pairs, byes) = pair_round(&entries, Format::Double);
+        assert_eq!(seeds(&pairs), vec![(1, 2)]);
+        assert!(byes.is_empty());
+    }
+
+    #[test]
+    fn the_knocked_out_are_never_paired() {
+        let mut entries = entries(4);
+        entries[3].losses = 1;
+        let (pairs, byes) = pair_round(&entries, Format::Single);
+        assert_eq!(seeds(&pairs), vec![(1, 3)]);
+        assert_eq!(byes.iter().map(|e| e.seed).collect::<Vec<_>>(), vec![2]);
+    }
+}
@@ -0,0 +1,270 @@
+use serenity::builder::CreateApplicationCommands;
+use serenity::client::Context;
+use serenity::framework::standard::{
+    macros::{command, group},
+    Args, CommandResult,
+};
+use serenity::model::application::command::CommandOptionType;
+use serenity::model::application::interaction::application_command::CommandDataOption;
+use serenity::model::channel::Message;
+
+use rusqlite::{params, Result};
+
+use crate::slash::{gladiator_option, option_str, Invocation};
+use crate::{
+    calc_modifier, find_character, get_character, is_owner, ludus, matchmaking, now, open_db,
+    purse, roller,
+};
+
+// What a session with the doctores costs, and how long before the next
+const TRAINING_FEE: i64 = 10;
+const TRAINING_COOLDOWN: i64 = 12 * 3600;
+// The top of calc_modifier's table
+const MAX_STAT: i8 = 18;
+// A session only pays off on a d20, plus Int, beating the stat by this much,
+// so the better a gladiator gets the slower they improve
+const TRAINING_DIFFICULTY: i8 = 4;
+const PROFICIENCY_DC: i8 = 14;
+// However many doctores and retired trainers a ludus keeps, a session only
+// gets so much better for them
+const MAX_TRAINER_BONUS: i64 = 4;
+const WEAPON: &str = "weapon";
+
+// Trainable stats by the name players use and their column. Luck can't be
+// trained
+const STATS: [(&str, &str); 5] = [
+    ("str", "strength"),
+    ("agi", "agility"),
+    ("sta", "stamina"),
+    ("per", "personality"),
+    ("int", "inteligence"),
+];
+
+#[group]
+#[commands(train)]
+struct Training;
+
+// Takes "str", "strength", "Str" and so on
+fn find_stat(regime: &str) -> Option<(&'static str, &'static str)> {
+    let regime = regime.to_lowercase();
+    STATS
+        .iter()
+        .find(|(short, _)| regime.starts_with(short))
+        .copied()
+}
+
+// The stat as stored, before any injury takes its toll
+fn get_stat(glad_id: i64, column: &str) -> Result<i8> {
+    let db = open_db()?;
+
+    db.query_row(
+        &format!("SELECT {} FROM glads WHERE id = ?1", column),
+        [glad_id],
+        |row| row.get(0),
+    )
+}
+
+fn get_trained_at(glad_id: i64) -> Result<i64> {
+    let db = open_db()?;
+
+    db.query_row(
+        "SELECT trained_at FROM glads WHERE id = ?1",
+        [glad_id],
+        |row| row.get(0),
+    )
+}
+
+fn set_trained_at(glad_id: i64) -> Result<()> {
+    let db = open_db()?;
+
+    db.execute(
+        "UPDATE glads SET trained_at = ?1 WHERE id = ?2",
+        params![now(), glad_id],
+    )?;
+
+    Ok(())
+}
+
+// Returns whether the stat went up, it never goes past the cap
+fn improve_stat(glad_id: i64, column: &str) -> Result<bool> {
+    let db = open_db()?;
+
+    let updated = db.execute(
+        &format!(
+            "UPDATE glads SET {0} = {0} + 1 WHERE id = ?1 AND {0} < ?2",
+            column
+        ),
+        params![glad_id, MAX_STAT],
+    )?;
+
+    Ok(updated == 1)
+}
+
+fn add_proficiency(glad_id: i64, weapon: &str) -> Result<()> {
+    let db = open_db()?;
+
+    db.execute(
+        "UPDATE glads SET proficiencies = CASE WHEN proficiencies = '' THEN ?1 ELSE proficiencies || '; ' || ?1 END WHERE id = ?2",
+        params![weapon, glad_id],
+    )?;
+
+    Ok(())
+}
+
+pub fn register(commands: &mut CreateApplicationCommands) -> &mut CreateApplicationCommands {
+    commands.create_application_command(|command| {
+        command
+            .name("train")
+            .description("Pay for a training session to improve a gladiator")
+            .create_option(|option| gladiator_option(option, "Your gladiator", true))
+            .create_option(|option| {
+                option
+                    .name("regime")
+                    .description("What to work on")
+                    .kind(CommandOptionType::String)
+                    .required(true);
+                for (stat, _) in STATS {
+                    option.add_string_choice(stat, stat);
+                }
+                option.add_string_choice(WEAPON, WEAPON)
+            })
+    })
+}
+
+pub async fn run(inv: &Invocation<'_>, options: &[CommandDataOption]) -> CommandResult {
+    let name = option_str(options, "gladiator").unwrap_or("");
+    train_gladiator(inv, name, option_str(options, "regime").unwrap_or("")).await
+}
+
+#[command]
+async fn train(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    // The regime comes last, after the gladiator's name
+    let mut words: Vec<&str> = args.raw().collect();
+    let regime = words.pop().unwrap_or("");
+    train_gladiator(
+        &Invocation::from_message(ctx, msg),
+        &words.join(" "),
+        regime,
+    )
+    .await
+}
+
+async fn train_gladiator(inv: &Invocation<'_>, name: &str, regime: &str) -> CommandResult {
+    let stat = find_stat(regime);
+    if stat.is_none() && !regime.eq_ignore_ascii_case(WEAPON) {
+        let names: Vec<&str> = STATS.iter().map(|(stat, _)| *stat).collect();
+        let out = format!(
+            "Train what? Choose one of: {}, {}",
+            names.join(", "),
+            WEAPON
+        );
+        inv.reply(&out).await?;
+        return Ok(());
+    }
+
+    let guild_id = match inv.guild_id {
+        Some(guild_id) => guild_id.0,
+        None => {
+            inv.reply("The doctores only take denarii kept in a server.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let glad = match find_character(
+        name,
+        inv.guild_id.map(|guild_id| guild_id.0),
+        inv.author.id.0,
+    )? {
+        Some(glad) if !is_owner(&glad, inv.author.id.0) => {
+            let out = format!("{} does not fight for you!", glad.name);
+            inv.reply(&out).await?;
+            return Ok(());
+        }
+        Some(glad) if !matchmaking::can_fight(&glad) => {
+            let out = format!("{} is in no state to train!", glad.name);
+            inv.reply(&out).await?;
+            return Ok(());
+        }
+        Some(glad) => glad,
+        None => {
+            inv.reply("No gladiator goes by that name.").await?;
+            return Ok(());
+        }
+    };
+
+    let rested_at = get_trained_at(glad.id)? + TRAINING_COOLDOWN;
+    if rested_at > now() {
+        let out = format!(
+            "{} is still aching from their last session, try again in {} hours.",
+            glad.name,
+            (rested_at - now() + 3599) / 3600
+        );
+        inv.reply(&out).await?;
+        return Ok(());
+    }
+    let stored = match stat {
+        Some((_, column)) => get_stat(glad.id, column)?,
+        None => 0,
+    };
+    if let Some((short, _)) = stat.filter(|_| stored >= MAX_STAT) {
+        let out = format!("{}'s {} can go no higher.", glad.name, short);
+        inv.reply(&out).await?;
+        return Ok(());
+    }
+    if stat.is_none() && glad.proficiencies.contains(&glad.weapon.name) {
+        let out = format!(
+            "{} has nothing left to learn with the {}.",
+            glad.name, glad.weapon.name
+        );
+        inv.reply(&out).await?;
+        return Ok(());
+    }
+    if !purse::debit(guild_id, inv.author.id.0, TRAINING_FEE)? {
+        let out = format!(
+            "The doctores want {} denarii, which you don't have.",
+            TRAINING_FEE
+        );
+        inv.reply(&out).await?;
+        return Ok(());
+    }
+    set_trained_at(glad.id)?;
+
+    // Every doctore in the gladiator's ludus helps the session along
+    let trainers = ludus::get_home(glad.guild_id, glad.owner_id)?
+        .map(|ludus| (ludus.trainers + ludus.veterans).min(MAX_TRAINER_BONUS) as i8)
+        .unwrap_or(0);
+    let roll = roller(1, 20) + calc_modifier(glad.inteligence) + trainers;
+    let out = match stat {
+        Some((short, column)) if roll > stored + TRAINING_DIFFICULTY => {
+            match improve_stat(glad.id, column)? {
+                // AC comes from agility as the gladiator is loaded, so a
+                // better modifier shows up in their guard straight away
+                true if get_character(glad.id)?.ac > glad.ac => format!(
+                    "{} trains hard and gains a point of {}, and their guard improves!",
+                    glad.name, short
+                ),
+                true => format!("{} trains hard and gains a point of {}!", glad.name, short),
+                false => format!("{}'s {} can go no higher.", glad.name, short),
+            }
+        }
+        Some((short, _)) => format!(
+            "{} sweats through the drills but their {} doesn't improve.",
+            glad.name, short
+        ),
+        None if roll >= PROFICIENCY_DC => {
+            add_proficiency(glad.id, &glad.weapon.name)?;
+            format!(
+                "{} masters the {}, and will strike truer with it!",
+                glad.name, glad.weapon.name
+            )
+        }
+        None => format!(
+            "{} drills with the {} but hasn't mastered it yet.",
+            glad.name, glad.weapon.name
+        ),
+    };
+    inv.reply(&out).await?;
+
+    Ok(())
+}